@@ -0,0 +1,113 @@
+## Slash commands, names must be lowercase without spaces.
+
+command-fx = fx
+    .description = Fx commands
+subcommand-create = create
+    .description = Create an fx command
+subcommand-tts = tts
+    .description = Create an fx command with text-to-speech
+subcommand-combo = combo
+    .description = Join segments into an fx command
+subcommand-info = info
+    .description = Show the details of an fx command
+subcommand-play = play
+    .description = Play an fx command
+option-name = name
+    .description = Name of the fx command
+option-description = description
+    .description = Description of the fx command
+option-source = source
+    .description = URL of a video, or use an attachment instead
+option-attachment = attachment
+    .description = Audio or video file to use instead of the source
+option-start = start
+    .description = Second to start at, 0 by default
+option-duration = duration
+    .description = Seconds to last, at most 20, 5 by default
+option-speed = speed
+    .description = Playback speed multiplier keeping the pitch, 1 by default
+option-pitch = pitch
+    .description = Semitones to shift the pitch by, keeping the speed
+option-reverse = reverse
+    .description = Play it backwards
+option-echo = echo
+    .description = Delay of the echo in milliseconds
+option-bass = bass
+    .description = Bass gain in dB
+option-fade-in = fade-in
+    .description = Seconds to fade in at the start
+option-fade-out = fade-out
+    .description = Seconds to fade out at the end
+option-context = waveform-context
+    .description = Also draw 10 seconds around the cut on the waveform
+option-trim = trim-silence
+    .description = Cut the silence off both ends
+option-text = text
+    .description = Text to read out
+option-voice = voice
+    .description = Voice of the speech engine, e.g. zh or en-us
+option-segments = segments
+    .description = Comma separated fx names or URL@start+length, with gap=seconds or xfade=seconds between
+
+## Previews and details of fx
+
+seconds = { $seconds }s
+embed-source = Source
+embed-title = Title
+embed-source-length = Source length
+embed-start = Start
+embed-length = Length
+embed-effects = Effects
+embed-trimmed = Trimmed silence
+embed-trimmed-value = { $leading } at the start, { $trailing } at the end
+button-create = Add
+button-listen = Listen
+button-cancel = Cancel
+nudge-start = Start { $seconds }s
+nudge-length = Length { $seconds }s
+
+## Making fx
+
+processing-chicken = Meow! Working on it. Chicken treats are on sale, you know what that means?
+processing-plain = Meow! Working on it
+processing-treats = Meow! I like chicken treats and canned food. Also... working on it
+progress-resolving = Meow! Looking up the source
+progress-queued-next = Meow! Waiting in line, you're next
+progress-queued = Meow! Waiting in line, { $ahead } fx ahead of yours
+progress-downloading = Meow! Downloading the source
+progress-downloading-percentage = Meow! Downloading the source ({ $percentage }%)
+progress-cutting = Meow! Cutting the fx
+progress-uploading = Meow! Uploading the preview
+invalid-command = Don't know WTF you are talking about. Meow!
+create-succeeded = Added!
+
+## Text commands
+
+usage-play = Usage: !play <URL>
+usage-seek = Usage: !seek <seconds>
+usage-loop = Usage: !loop [on|off]
+invalid-channel-id = Invalid id
+
+## Failures
+
+error = Mew... { $reason } (error code: { $id })
+error-fx-not-found = No such fx command
+error-fx-exists = An fx command of the same name already exists
+error-past-end = The cut goes past the end of the source, which is { $seconds }s long
+error-composite = The segments are written wrong: { $why }
+error-part-not-found = Can't find the fx `{ $name }` in the segments
+error-user-not-in-voice = You are not in any voice channel
+error-bot-not-in-voice = Not in a voice channel
+error-cannot-join = Can't join your channel
+error-not-playing = Nothing is playing
+error-not-seekable = This track can't be sought
+error-cannot-play = Can't play this fx
+error-undecodable = The media of this fx is broken or of an unsupported format
+error-too-many-pending = You have too many fx in line, try again later
+error-source-unavailable = Can't get the source, please check the URL
+error-unsupported-upload = The uploaded file is not supported
+error-upload-gone = The uploaded file is gone, please upload it again
+error-speech-unavailable = Text-to-speech is not available right now
+error-render = Can't cut this fx
+error-stale = I forgot about this, please run the command again
+error-internal = Something went wrong on my side, please try again later
@@ -0,0 +1,113 @@
+## Slash commands, the names here are what the handlers look options up by.
+
+command-fx = fx
+    .description = 音效指令
+subcommand-create = create
+    .description = 創立音效指令
+subcommand-tts = tts
+    .description = 用文字轉語音創立音效指令
+subcommand-combo = combo
+    .description = 把多個片段接成一個音效指令
+subcommand-info = info
+    .description = 查看音效指令的資訊
+subcommand-play = play
+    .description = 播放音效指令
+option-name = 名稱
+    .description = 音效指令的名稱
+option-description = 描述
+    .description = 音效指令的描述
+option-source = 來源
+    .description = 填入影片的URL，或改用附件
+option-attachment = 附件
+    .description = 上傳音訊或影片檔，取代來源
+option-start = 開始秒數
+    .description = 開始秒數，預設0秒開始
+option-duration = 持續秒數
+    .description = 持續秒數，最大20秒，預設5秒
+option-speed = 速度
+    .description = 播放速度的倍數，不改變音高，預設1
+option-pitch = 音高
+    .description = 升降幾個半音，不改變速度
+option-reverse = 倒轉
+    .description = 倒著播放
+option-echo = 回音
+    .description = 回音的延遲毫秒數
+option-bass = 重低音
+    .description = 低音增益的分貝數
+option-fade-in = 淡入秒數
+    .description = 開頭淡入的秒數
+option-fade-out = 淡出秒數
+    .description = 結尾淡出的秒數
+option-context = 波形前後文
+    .description = 波形圖也畫出前後10秒
+option-trim = 修剪靜音
+    .description = 自動剪掉開頭跟結尾的靜音
+option-text = 文字
+    .description = 要唸出來的文字
+option-voice = 聲音
+    .description = 語音引擎的聲音，例如 zh 或 en-us
+option-segments = 片段
+    .description = 以逗號分隔的音效名稱或URL@開始+長度，可穿插 gap=秒數 或 xfade=秒數
+
+## Previews and details of fx
+
+seconds = { $seconds }秒
+embed-source = 來源
+embed-title = 標題
+embed-source-length = 來源長度
+embed-start = 開始秒數
+embed-length = 長度
+embed-effects = 效果
+embed-trimmed = 修剪靜音
+embed-trimmed-value = 開頭{ $leading }，結尾{ $trailing }
+button-create = 新增
+button-listen = 試聽
+button-cancel = 取消
+nudge-start = 開始 { $seconds }秒
+nudge-length = 長度 { $seconds }秒
+
+## Making fx
+
+processing-chicken = 喵! 本毛正在處理你的要求，雞肉條在特價噎，你應該知道本毛在說什麼？
+processing-plain = 喵! 本毛正在處理你的要求
+processing-treats = 喵! 本毛喜歡雞肉條跟罐罐。還有...本毛正在處理你的要求
+progress-resolving = 喵! 本毛正在查詢來源
+progress-queued-next = 喵! 本毛正在排隊，下一個就輪到你
+progress-queued = 喵! 本毛正在排隊，前面還有{ $ahead }個音效
+progress-downloading = 喵! 本毛正在下載來源
+progress-downloading-percentage = 喵! 本毛正在下載來源 ({ $percentage }%)
+progress-cutting = 喵! 本毛正在剪輯音效
+progress-uploading = 喵! 本毛正在上傳預覽
+invalid-command = 本毛Don't know WTF are you talking about. 喵!
+create-succeeded = 新增成功!
+
+## Text commands
+
+usage-play = 用法!play <URL>
+usage-seek = 用法!seek <秒數>
+usage-loop = 用法!loop [on|off]
+invalid-channel-id = 無效的id
+
+## Failures
+
+error = 喵嗚... { $reason } (錯誤代碼: { $id })
+error-fx-not-found = 本毛找不到此指令
+error-fx-exists = 已經有同名的音效指令了
+error-past-end = 超過來源的長度了，來源只有{ $seconds }秒
+error-composite = 片段寫錯了: { $why }
+error-part-not-found = 本毛找不到片段裡的音效 `{ $name }`
+error-user-not-in-voice = 您沒有在任何語音頻道
+error-bot-not-in-voice = 本毛不在語音頻道
+error-cannot-join = 本毛無法加入您的頻道
+error-not-playing = 本毛沒有在播放
+error-not-seekable = 這個音軌不能跳轉
+error-cannot-play = 本毛播放不了這個音效
+error-undecodable = 這個音效的檔案壞了或是格式不支援
+error-too-many-pending = 你排了太多音效了，等等再試
+error-source-unavailable = 本毛拿不到來源，請確認網址
+error-unsupported-upload = 不支援上傳的檔案
+error-upload-gone = 上傳的檔案不見了，請重新上傳
+error-speech-unavailable = 本毛現在不會說話
+error-render = 本毛剪不出這個音效
+error-stale = 本毛忘了，請重新呼叫指令
+error-internal = 本毛出了點問題，請稍後再試
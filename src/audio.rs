@@ -1,68 +1,471 @@
+use rodio::source::UniformSourceIterator;
 use serenity::{
     cache::FromStrAndCache,
     client::Context,
     model::{
-        channel::{Channel, ChannelType, Message},
+        channel::{Channel, ChannelType},
         id::{ChannelId, GuildId},
     },
+    prelude::TypeMapKey,
+};
+use songbird::{
+    input::{
+        children_to_reader, codec::OpusDecoderState, reader::MediaSource, Codec, Container, Input,
+        Reader,
+    },
+    tracks::TrackHandle,
 };
-use songbird::input::{reader::MediaSource, Codec, Container, Input, Reader};
 use std::{
-    ffi::OsStr,
-    fmt::Debug,
-    io::{Read, Seek, SeekFrom},
+    collections::HashMap,
+    fmt::{self, Debug, Display},
+    fs::File,
+    io::{self, BufReader, Cursor, Read, Seek, SeekFrom},
+    path::Path,
+    process::Stdio,
+    sync::Arc,
+};
+use tokio::io::AsyncRead;
+use tokio_util::io::SyncIoBridge;
+
+use crate::{
+    discord::{check_serenity_result, AuthorVoiceChannelFinder, Localized, Replyable},
+    error::{Reason, UserError},
+    ioutils::{SeekableStream, SharedReader},
+    tools::Toolchain,
 };
 
-use crate::discord::{check_serenity_result, AuthorVoiceChannelFinder, Replyable};
+/// Songbird takes raw PCM as interleaved stereo at this rate.
+const SONGBIRD_SAMPLE_RATE: u32 = 48000;
+const SONGBIRD_CHANNELS: u16 = 2;
+
+/// Decode media of any format rodio recognizes, that is WAV, FLAC, Ogg Vorbis and mp3, to
+/// songbird PCM.
+fn decode<R: Read + Seek + Send + 'static>(
+    source: R,
+) -> Result<RodioMediaSource<rodio::Decoder<SharedReader<R>>>, PlayError> {
+    let source = SharedReader::new(source);
+    let decoder = rodio::Decoder::new(source.clone()).map_err(|why| {
+        log::error!("fail to decode the media, {:?}", why);
+        PlayError::Undecodable
+    })?;
+    Ok(RodioMediaSource::new(decoder).restartable(move || {
+        let mut source = source.clone();
+        source.seek(SeekFrom::Start(0))?;
+        rodio::Decoder::new(source)
+            .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", why)))
+    }))
+}
+
+pub fn decode_to_songbird_input<R: Read + Seek + Send + 'static>(
+    source: R,
+) -> Result<Input, PlayError> {
+    let reader = Reader::Extension(Box::new(decode(source)?));
+    Ok(Input::new(true, reader, Codec::Pcm, Container::Raw, None))
+}
+
+/// Hand the Opus packets of an Ogg stream to songbird as they are, so they are sent without
+/// decoding when nothing else is mixed in.
+pub fn ogg_opus_to_songbird_input<R: Read + Seek + Send + 'static>(
+    source: R,
+) -> Result<Input, PlayError> {
+    dca_to_songbird_input(Box::new(OggOpusMediaSource::new(source)))
+}
 
-pub fn mp3_to_songbird_input<R: Read + Seek + Send + Sync + 'static>(source: R) -> Input {
-    let decoder = rodio::Decoder::new_mp3(source).unwrap();
-    let source = RodioMediaSource { decoder };
-    let reader = Reader::Extension(Box::new(source));
-    Input::new(true, reader, Codec::Pcm, Container::Raw, None)
+/// Opus packets framed like DCA.
+fn dca_to_songbird_input(source: Box<dyn MediaSource + Send>) -> Result<Input, PlayError> {
+    let decoder = OpusDecoderState::new().map_err(|why| {
+        log::error!("fail to create an opus decoder, {:?}", why);
+        PlayError::CannotPlay
+    })?;
+    Ok(Input::new(
+        true,
+        Reader::Extension(source),
+        Codec::Opus(decoder),
+        Container::Dca { first_frame: 0 },
+        None,
+    ))
 }
 
-struct RodioMediaSource<R>
+/// Whether `source` starts with the first page of an Ogg Opus stream, it's rewound either way.
+fn is_ogg_opus<R: Read + Seek>(source: &mut R) -> io::Result<bool> {
+    // a first page holds a single segment, so its packet starts after 28 bytes
+    let mut head = [0; 36];
+    let mut read = 0;
+    while read < head.len() {
+        match source.read(&mut head[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    source.seek(SeekFrom::Start(0))?;
+    Ok(read == head.len() && &head[..4] == b"OggS" && &head[28..] == b"OpusHead")
+}
+
+/// Start playing a stream of stored media that may still be produced, Ogg Opus is passed
+/// through and anything else is decoded by sniffing its format. Returns as soon as the first
+/// frames are available, the rest is pulled by songbird while playing.
+pub async fn media_stream_to_songbird_input<R>(source: R) -> Result<Input, PlayError>
 where
-    R: Read + Seek + Send + Sync,
+    R: AsyncRead + Send + Unpin + 'static,
 {
-    decoder: rodio::Decoder<R>,
+    let mut source = SeekableStream::new(SyncIoBridge::new(source));
+    // reading the first frames blocks
+    tokio::task::spawn_blocking(move || match is_ogg_opus(&mut source) {
+        Ok(true) => ogg_opus_to_songbird_input(source),
+        Ok(false) => decode_to_songbird_input(source),
+        Err(why) => {
+            log::error!("fail to read the media stream, {:?}", why);
+            Err(PlayError::CannotPlay)
+        }
+    })
+    .await
+    .map_err(|why| {
+        log::error!("fail to decode the media stream, {:?}", why);
+        PlayError::CannotPlay
+    })?
+}
+
+/// Media decoded ahead of playing, songbird starts on it without waiting for anything.
+#[derive(Clone)]
+pub enum DecodedMedia {
+    /// Opus packets framed like DCA
+    Opus(Arc<[u8]>),
+    /// interleaved stereo i16 at 48kHz
+    Pcm(Arc<[u8]>),
+}
+
+impl DecodedMedia {
+    /// Bytes held in memory.
+    pub fn size(&self) -> usize {
+        match self {
+            DecodedMedia::Opus(frames) => frames.len(),
+            DecodedMedia::Pcm(samples) => samples.len(),
+        }
+    }
+
+    pub fn to_songbird_input(&self) -> Result<Input, PlayError> {
+        match self {
+            DecodedMedia::Opus(frames) => dca_to_songbird_input(Box::new(MemoryMediaSource {
+                frames: Some(FrameIndex::of(frames)),
+                data: Cursor::new(frames.clone()),
+            })),
+            DecodedMedia::Pcm(samples) => {
+                let source = MemoryMediaSource {
+                    data: Cursor::new(samples.clone()),
+                    frames: None,
+                };
+                let reader = Reader::Extension(Box::new(source));
+                Ok(Input::new(true, reader, Codec::Pcm, Container::Raw, None))
+            }
+        }
+    }
 }
 
-impl<R> MediaSource for RodioMediaSource<R>
+/// Decode all of a stream of stored media, the formats are the ones
+/// [`media_stream_to_songbird_input`] plays.
+pub async fn decode_media_stream<R>(source: R) -> Result<DecodedMedia, PlayError>
 where
-    R: Read + Seek + Send + Sync,
+    R: AsyncRead + Send + Unpin + 'static,
 {
+    let mut source = SeekableStream::new(SyncIoBridge::new(source));
+    tokio::task::spawn_blocking(move || {
+        let mut decoded = vec![];
+        let read = match is_ogg_opus(&mut source) {
+            Ok(true) => OggOpusMediaSource::new(source)
+                .read_to_end(&mut decoded)
+                .map(|_| DecodedMedia::Opus(decoded.into())),
+            Ok(false) => decode(source)?
+                .read_to_end(&mut decoded)
+                .map(|_| DecodedMedia::Pcm(decoded.into())),
+            Err(why) => Err(why),
+        };
+        read.map_err(|why| {
+            log::error!("fail to read the media stream, {:?}", why);
+            PlayError::CannotPlay
+        })
+    })
+    .await
+    .map_err(|why| {
+        log::error!("fail to decode the media stream, {:?}", why);
+        PlayError::CannotPlay
+    })?
+}
+
+/// Offsets of the frames of Opus packets framed like DCA, so seeking lands on the start of a frame
+/// rather than in the middle of one.
+#[derive(Default)]
+struct FrameIndex(Vec<u64>);
+
+impl FrameIndex {
+    fn of(frames: &[u8]) -> Self {
+        let mut index = Self::default();
+        let mut offset = 0;
+        while offset + 2 <= frames.len() {
+            index.0.push(offset as u64);
+            let len = i16::from_le_bytes([frames[offset], frames[offset + 1]]);
+            offset += 2 + len.max(0) as usize;
+        }
+        index
+    }
+
+    fn push(&mut self, offset: u64) {
+        self.0.push(offset);
+    }
+
+    /// Start of the frame `position` is in.
+    fn frame_start(&self, position: u64) -> u64 {
+        match self.0.binary_search(&position) {
+            Ok(_) => position,
+            Err(0) => 0,
+            Err(next) => self.0[next - 1],
+        }
+    }
+}
+
+/// Data already in memory.
+struct MemoryMediaSource {
+    data: Cursor<Arc<[u8]>>,
+    /// frames of Opus packets, seeking moves to their starts
+    frames: Option<FrameIndex>,
+}
+
+impl Read for MemoryMediaSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.data.read(buf)
+    }
+}
+
+impl Seek for MemoryMediaSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = self.data.seek(pos)?;
+        match &self.frames {
+            Some(frames) if position < self.data.get_ref().len() as u64 => {
+                let start = frames.frame_start(position);
+                self.data.set_position(start);
+                Ok(start)
+            }
+            _ => Ok(position),
+        }
+    }
+}
+
+impl MediaSource for MemoryMediaSource {
     fn is_seekable(&self) -> bool {
         true
     }
 
+    fn byte_len(&self) -> Option<u64> {
+        Some(self.data.get_ref().len() as u64)
+    }
+}
+
+/// Opus packets of an Ogg stream framed like DCA, with the length of each packet before it as a
+/// little-endian i16. The frames read are kept so the source can be rewound, fx are short enough
+/// for that.
+struct OggOpusMediaSource<R>
+where
+    R: Read + Seek + Send,
+{
+    packets: ogg::PacketReader<R>,
+    framed: Vec<u8>,
+    frames: FrameIndex,
+    position: usize,
+}
+
+impl<R> OggOpusMediaSource<R>
+where
+    R: Read + Seek + Send,
+{
+    fn new(source: R) -> Self {
+        Self {
+            packets: ogg::PacketReader::new(source),
+            framed: vec![],
+            frames: FrameIndex::default(),
+            position: 0,
+        }
+    }
+
+    /// Frame the next audio packet after those read, false at the end of the stream.
+    fn next_frame(&mut self) -> io::Result<bool> {
+        loop {
+            let packet = match self.packets.read_packet() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => return Ok(false),
+                Err(why) => return Err(io::Error::new(io::ErrorKind::InvalidData, why)),
+            };
+            if packet.data.starts_with(b"OpusHead") || packet.data.starts_with(b"OpusTags") {
+                continue;
+            }
+            let len = i16::try_from(packet.data.len())
+                .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why))?;
+            self.frames.push(self.framed.len() as u64);
+            self.framed.extend_from_slice(&len.to_le_bytes());
+            self.framed.extend_from_slice(&packet.data);
+            return Ok(true);
+        }
+    }
+}
+
+impl<R> MediaSource for OggOpusMediaSource<R>
+where
+    R: Read + Seek + Send,
+{
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl<R> Seek for OggOpusMediaSource<R>
+where
+    R: Read + Seek + Send,
+{
+    /// Moves to the start of the frame the position is in, reading the packets up to it.
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.position as i64 + offset,
+            SeekFrom::End(offset) => {
+                while self.next_frame()? {}
+                self.framed.len() as i64 + offset
+            }
+        };
+        if target < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek to a negative position",
+            ));
+        }
+        let target = target as usize;
+        while self.framed.len() <= target && self.next_frame()? {}
+        self.position = if target < self.framed.len() {
+            self.frames.frame_start(target as u64) as usize
+        } else {
+            self.framed.len()
+        };
+        Ok(self.position as u64)
+    }
+}
+
+impl<R> Read for OggOpusMediaSource<R>
+where
+    R: Read + Seek + Send,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position == self.framed.len() && !self.next_frame()? {
+            return Ok(0);
+        }
+        let n = (self.framed.len() - self.position).min(buf.len());
+        buf[..n].copy_from_slice(&self.framed[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+/// Recreates a rodio source from its start.
+type Restart<S> = Box<dyn FnMut() -> io::Result<S> + Send>;
+
+/// PCM of a rodio source converted to what songbird expects, whatever the rate and channels of
+/// the source are.
+struct RodioMediaSource<S>
+where
+    S: rodio::Source<Item = i16> + Send,
+{
+    samples: UniformSourceIterator<S, i16>,
+    /// Seeking backwards decodes again from the start, sources that can't be recreated are only
+    /// played through.
+    restart: Option<Restart<S>>,
+    /// Bytes of PCM read so far.
+    position: u64,
+}
+
+impl<S> RodioMediaSource<S>
+where
+    S: rodio::Source<Item = i16> + Send,
+{
+    fn new(source: S) -> Self {
+        Self {
+            samples: Self::convert(source),
+            restart: None,
+            position: 0,
+        }
+    }
+
+    fn restartable(self, restart: impl FnMut() -> io::Result<S> + Send + 'static) -> Self {
+        Self {
+            restart: Some(Box::new(restart)),
+            ..self
+        }
+    }
+
+    fn convert(source: S) -> UniformSourceIterator<S, i16> {
+        UniformSourceIterator::new(source, SONGBIRD_CHANNELS, SONGBIRD_SAMPLE_RATE)
+    }
+}
+
+impl<S> MediaSource for RodioMediaSource<S>
+where
+    S: rodio::Source<Item = i16> + Send,
+{
+    fn is_seekable(&self) -> bool {
+        self.restart.is_some()
+    }
+
     fn byte_len(&self) -> Option<u64> {
         None
     }
 }
 
-impl<R> Seek for RodioMediaSource<R>
+impl<S> Seek for RodioMediaSource<S>
 where
-    R: Read + Seek + Send + Sync,
+    S: rodio::Source<Item = i16> + Send,
 {
-    fn seek(&mut self, _pos: SeekFrom) -> std::io::Result<u64> {
-        Err(std::io::Error::new(
-            std::io::ErrorKind::Unsupported,
-            "unsupported",
-        ))
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.position as i64 + offset,
+            SeekFrom::End(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "the length of decoded media is unknown",
+                ))
+            }
+        };
+        if target < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek to a negative position",
+            ));
+        }
+        // stay on whole frames, so the channels aren't swapped
+        let frame = 2 * SONGBIRD_CHANNELS as u64;
+        let target = target as u64 / frame * frame;
+        if target < self.position {
+            let restart = self.restart.as_mut().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::Unsupported, "the source cannot be rewound")
+            })?;
+            self.samples = Self::convert(restart()?);
+            self.position = 0;
+        }
+        while self.position < target && self.samples.next().is_some() {
+            self.position += 2;
+        }
+        Ok(self.position)
     }
 }
 
-impl<R> Read for RodioMediaSource<R>
+impl<S> Read for RodioMediaSource<S>
 where
-    R: Read + Seek + Send + Sync,
+    S: rodio::Source<Item = i16> + Send,
 {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         let sample_count = buf.len() / 2;
         let mut count = 0;
         for _ in 0..sample_count {
-            let sample = self.decoder.next();
+            let sample = self.samples.next();
             match sample {
                 None => {
                     break;
@@ -75,6 +478,7 @@ where
                 }
             }
         }
+        self.position += count as u64;
         Ok(count)
     }
 }
@@ -93,24 +497,19 @@ pub async fn join_channel(
     result
 }
 
-pub async fn try_join_authors_channel<I: Replyable + AuthorVoiceChannelFinder>(
+pub async fn try_join_authors_channel<I: Replyable + AuthorVoiceChannelFinder + Localized>(
     ctx: &Context,
     intent: I,
 ) {
-    match intent.find_user_voice_channel().await {
+    let error = match intent.find_user_voice_channel().await {
         Ok(Some((guild_id, channel_id))) => match join_channel(ctx, guild_id, channel_id).await {
-            Ok(()) => (),
-            Err(err) => {
-                log::error!("fail to join voice channel, {:?}", err)
-            }
+            Ok(()) => return,
+            Err(why) => UserError::from(why),
         },
-        Ok(None) => {
-            check_serenity_result(intent.reply(format!("您沒有在任何語音頻道").as_str()).await);
-        }
-        Err(why) => {
-            log::error!("fail to find user's voice channel, {:?}", why);
-        }
-    }
+        Ok(None) => UserError::new(Reason::UserNotInVoice, &"the author is not in voice"),
+        Err(why) => UserError::from(why),
+    };
+    check_serenity_result(intent.reply(&error.message(intent.locale())).await);
 }
 
 pub async fn try_parse_voice_channel_id(ctx: &Context, id: &str) -> Option<ChannelId> {
@@ -138,6 +537,25 @@ pub async fn try_parse_voice_channel_id(ctx: &Context, id: &str) -> Option<Chann
 pub enum PlayError {
     NotInChannel,
     CannotPlay,
+    /// The media is corrupted or of a format that can't be decoded.
+    Undecodable,
+}
+
+impl Display for PlayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayError::NotInChannel => write!(f, "not in a voice channel"),
+            PlayError::CannotPlay => write!(f, "cannot play the media"),
+            PlayError::Undecodable => write!(f, "the media is corrupted or of an unknown format"),
+        }
+    }
+}
+
+/// The track last played in each guild, kept so it can be sought or looped.
+pub struct NowPlaying;
+
+impl TypeMapKey for NowPlaying {
+    type Value = HashMap<GuildId, TrackHandle>;
 }
 
 pub async fn try_play_source(
@@ -146,44 +564,118 @@ pub async fn try_play_source(
     source: Input,
 ) -> Result<(), PlayError> {
     let manager = songbird::get(ctx).await.unwrap();
-    match manager.get(guild_id) {
+    let track = match manager.get(guild_id) {
         Some(handler_lock) => {
             let mut handler = handler_lock.lock().await;
-            handler.play_only_source(source);
-            Ok(())
+            handler.play_only_source(source)
         }
-        None => Err(PlayError::NotInChannel),
-    }
+        None => return Err(PlayError::NotInChannel),
+    };
+    ctx.data
+        .write()
+        .await
+        .entry::<NowPlaying>()
+        .or_insert_with(HashMap::new)
+        .insert(guild_id, track);
+    Ok(())
 }
 
-pub async fn try_play_ytdl(
-    ctx: &Context,
-    msg: &Message,
-    url: &str,
-    guild_id: GuildId,
-) -> Result<(), PlayError> {
-    let source = match songbird::ytdl(&url).await {
+pub async fn now_playing(ctx: &Context, guild_id: GuildId) -> Option<TrackHandle> {
+    ctx.data
+        .read()
+        .await
+        .get::<NowPlaying>()
+        .and_then(|tracks| tracks.get(&guild_id))
+        .cloned()
+}
+
+/// Stream the audio of `url` through the configured downloader and ffmpeg.
+fn ytdl_input(tools: &Toolchain, url: &str) -> io::Result<Input> {
+    let mut downloader = tools
+        .downloader
+        .std_command()
+        .args([
+            "-f",
+            "webm[abr>0]/bestaudio/best",
+            "--no-playlist",
+            "-o",
+            "-",
+        ])
+        .arg(url)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let ffmpeg = tools
+        .ffmpeg
+        .std_command()
+        .stdin(downloader.stdout.take().unwrap())
+        .stderr(Stdio::null())
+        .stdout(Stdio::piped())
+        .args(["-i", "-", "-f", "f32le", "-ac", "2", "-ar", "48000"])
+        .args(["-acodec", "pcm_f32le", "-"])
+        .spawn();
+    let ffmpeg = match ffmpeg {
+        Ok(ffmpeg) => ffmpeg,
+        Err(why) => {
+            let _result = downloader.kill();
+            return Err(why);
+        }
+    };
+    Ok(Input::new(
+        true,
+        children_to_reader::<f32>(vec![downloader, ffmpeg]),
+        Codec::FloatPcm,
+        Container::Raw,
+        None,
+    ))
+}
+
+pub async fn try_play_ytdl(ctx: &Context, url: &str, guild_id: GuildId) -> Result<(), PlayError> {
+    let tools = ctx
+        .data
+        .read()
+        .await
+        .get::<Toolchain>()
+        .cloned()
+        .expect("toolchain is not registered");
+    let source = match ytdl_input(&tools, url) {
         Ok(source) => source,
         Err(why) => {
             log::error!("cannot play youtube, url: {:?}", why);
-            check_serenity_result(msg.reply(&ctx.http, "無法播放QAQ").await);
-            return Ok(());
+            return Err(PlayError::CannotPlay);
         }
     };
     try_play_source(ctx, guild_id, source).await
 }
 
-pub async fn try_play_file<P: AsRef<OsStr> + Debug>(
+/// Play a local file. Formats rodio reads are decoded here so the track can be sought, the others
+/// are played through ffmpeg.
+pub async fn try_play_file<P: AsRef<Path> + Debug>(
     ctx: &Context,
     guild_id: GuildId,
     path: P,
 ) -> Result<(), PlayError> {
-    let source = match songbird::ffmpeg(&path).await {
-        Ok(input) => input,
-        Err(err) => {
-            log::error!("cannot play {:?} sound effect, {:?}", path, err);
-            return Err(PlayError::CannotPlay);
-        }
+    let path = path.as_ref().to_owned();
+    let file = path.clone();
+    let decoded = tokio::task::spawn_blocking(move || {
+        let opened = File::open(&file).map_err(|why| {
+            log::error!("cannot play {:?} sound effect, {:?}", file, why);
+            PlayError::CannotPlay
+        })?;
+        decode_to_songbird_input(BufReader::new(opened))
+    })
+    .await
+    .map_err(|why| {
+        log::error!("fail to decode the file, {:?}", why);
+        PlayError::CannotPlay
+    })?;
+    let source = match decoded {
+        Err(PlayError::Undecodable) => songbird::ffmpeg(&path).await.map_err(|why| {
+            log::error!("cannot play {:?} sound effect, {:?}", path, why);
+            PlayError::CannotPlay
+        })?,
+        decoded => decoded?,
     };
     try_play_source(ctx, guild_id, source).await
 }
@@ -194,3 +686,194 @@ pub async fn stop_for_guild(ctx: &Context, guild_id: GuildId) {
         call.lock().await.stop()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ogg::{PacketWriteEndInfo, PacketWriter};
+    use rodio::buffer::SamplesBuffer;
+    use songbird::input::reader::MediaSource;
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+    use std::sync::Arc;
+    use test_case::test_case;
+
+    use super::{
+        decode, decode_media_stream, is_ogg_opus, DecodedMedia, FrameIndex, MemoryMediaSource,
+        OggOpusMediaSource, RodioMediaSource, SONGBIRD_CHANNELS, SONGBIRD_SAMPLE_RATE,
+    };
+
+    /// Seconds of songbird PCM read from a generated source of `secs` seconds.
+    fn output_secs(channels: u16, sample_rate: u32, secs: u32) -> f64 {
+        let samples: Vec<i16> = (0..channels as u32 * sample_rate * secs)
+            .map(|i| (i % 64) as i16 * 256)
+            .collect();
+        let mut source = RodioMediaSource::new(SamplesBuffer::new(channels, sample_rate, samples));
+        let mut pcm = vec![];
+        source.read_to_end(&mut pcm).unwrap();
+        let bytes_per_sec = 2 * SONGBIRD_CHANNELS as usize * SONGBIRD_SAMPLE_RATE as usize;
+        pcm.len() as f64 / bytes_per_sec as f64
+    }
+
+    #[test_case(1, 22050; "mono 22kHz")]
+    #[test_case(2, 22050; "stereo 22kHz")]
+    #[test_case(1, 44100; "mono 44.1kHz")]
+    #[test_case(2, 44100; "stereo 44.1kHz")]
+    #[test_case(2, 48000; "stereo 48kHz")]
+    fn test_output_keeps_duration(channels: u16, sample_rate: u32) {
+        let secs = output_secs(channels, sample_rate, 2);
+        assert!((secs - 2.0).abs() < 0.01, "{}s instead of 2s", secs);
+    }
+
+    /// A mono 22050Hz WAV of `secs` seconds.
+    fn wav(secs: u32) -> Cursor<Vec<u8>> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 22050,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut wav = Cursor::new(vec![]);
+        let mut writer = hound::WavWriter::new(&mut wav, spec).unwrap();
+        for i in 0..22050 * secs {
+            writer.write_sample((i % 64) as i16 * 256).unwrap();
+        }
+        writer.finalize().unwrap();
+        wav.set_position(0);
+        wav
+    }
+
+    #[test]
+    fn test_decode_wav() {
+        let mut pcm = vec![];
+        decode(wav(1)).unwrap().read_to_end(&mut pcm).unwrap();
+        let bytes_per_sec = 2 * SONGBIRD_CHANNELS as usize * SONGBIRD_SAMPLE_RATE as usize;
+        let secs = pcm.len() as f64 / bytes_per_sec as f64;
+        assert!((secs - 1.0).abs() < 0.01, "{}s instead of 1s", secs);
+    }
+
+    #[test_case(SeekFrom::Start(0), 0; "back to the start")]
+    #[test_case(SeekFrom::Start(48002), 48000; "back to a whole frame")]
+    #[test_case(SeekFrom::Current(-4), 95996; "back by a frame")]
+    #[test_case(SeekFrom::Start(150000), 150000; "forward")]
+    fn test_seek_decoded(pos: SeekFrom, expected: u64) {
+        let mut pcm = vec![];
+        decode(wav(1)).unwrap().read_to_end(&mut pcm).unwrap();
+        let mut source = decode(wav(1)).unwrap();
+        let mut head = vec![0; 96000];
+        source.read_exact(&mut head).unwrap();
+        assert_eq!(source.seek(pos).unwrap(), expected);
+        let mut rest = vec![];
+        source.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, pcm[expected as usize..]);
+    }
+
+    #[test]
+    fn test_seek_without_restart() {
+        let samples = SamplesBuffer::new(1, SONGBIRD_SAMPLE_RATE, vec![0i16; 48000]);
+        let mut source = RodioMediaSource::new(samples);
+        assert!(!source.is_seekable());
+        let mut head = [0; 64];
+        source.read_exact(&mut head).unwrap();
+        assert!(source.seek(SeekFrom::Start(0)).is_err());
+        assert_eq!(source.seek(SeekFrom::Start(128)).unwrap(), 128);
+    }
+
+    fn ogg_opus(packets: &[&[u8]]) -> Vec<u8> {
+        let mut writer = PacketWriter::new(Cursor::new(vec![]));
+        let mut head = b"OpusHead".to_vec();
+        head.extend_from_slice(&[1, 2, 0x38, 0x01, 0x80, 0xbb, 0, 0, 0, 0, 0]);
+        writer
+            .write_packet(head.into_boxed_slice(), 1, PacketWriteEndInfo::EndPage, 0)
+            .unwrap();
+        writer
+            .write_packet(
+                Box::from(&b"OpusTags"[..]),
+                1,
+                PacketWriteEndInfo::EndPage,
+                0,
+            )
+            .unwrap();
+        for (index, packet) in packets.iter().enumerate() {
+            let end = if index + 1 == packets.len() {
+                PacketWriteEndInfo::EndStream
+            } else {
+                PacketWriteEndInfo::NormalPacket
+            };
+            writer
+                .write_packet(Box::from(*packet), 1, end, 960 * (index as u64 + 1))
+                .unwrap();
+        }
+        writer.into_inner().into_inner()
+    }
+
+    #[test]
+    fn test_sniff_ogg_opus() {
+        assert!(is_ogg_opus(&mut Cursor::new(ogg_opus(&[b"frame"]))).unwrap());
+        assert!(!is_ogg_opus(&mut Cursor::new(b"ID3\x03".to_vec())).unwrap());
+    }
+
+    #[test]
+    fn test_ogg_opus_packets_are_framed() {
+        let mut source = OggOpusMediaSource::new(Cursor::new(ogg_opus(&[b"abc", b"de"])));
+        let mut frames = vec![];
+        source.read_to_end(&mut frames).unwrap();
+        assert_eq!(b"\x03\x00abc\x02\x00de".to_vec(), frames);
+    }
+
+    const FRAMED: &[u8] = b"\x03\x00abc\x02\x00de\x04\x00fghi";
+
+    #[test_case(SeekFrom::Start(0), 0; "back to the start")]
+    #[test_case(SeekFrom::Current(-1), 0; "back into the frame read")]
+    #[test_case(SeekFrom::Start(7), 5; "into a frame")]
+    #[test_case(SeekFrom::Start(12), 9; "into a frame not read yet")]
+    #[test_case(SeekFrom::End(0), 15; "to the end")]
+    fn test_seek_ogg_opus(pos: SeekFrom, expected: u64) {
+        let ogg = ogg_opus(&[b"abc", b"de", b"fghi"]);
+        let mut source = OggOpusMediaSource::new(Cursor::new(ogg));
+        assert!(source.is_seekable());
+        let mut head = [0; 5];
+        source.read_exact(&mut head).unwrap();
+        assert_eq!(expected, source.seek(pos).unwrap());
+        let mut rest = vec![];
+        source.read_to_end(&mut rest).unwrap();
+        assert_eq!(FRAMED[expected as usize..], rest);
+    }
+
+    #[test_case(SeekFrom::Start(0), 0; "to the start")]
+    #[test_case(SeekFrom::Start(6), 5; "into a frame")]
+    #[test_case(SeekFrom::Start(9), 9; "to a frame")]
+    #[test_case(SeekFrom::End(0), 15; "to the end")]
+    fn test_seek_decoded_opus(pos: SeekFrom, expected: u64) {
+        let frames: Arc<[u8]> = FRAMED.into();
+        let mut source = MemoryMediaSource {
+            frames: Some(FrameIndex::of(&frames)),
+            data: Cursor::new(frames),
+        };
+        assert!(source.is_seekable());
+        assert_eq!(expected, source.seek(pos).unwrap());
+        let mut rest = vec![];
+        source.read_to_end(&mut rest).unwrap();
+        assert_eq!(FRAMED[expected as usize..], rest);
+    }
+
+    #[tokio::test]
+    async fn test_decode_ogg_opus_stream() {
+        let media = decode_media_stream(Cursor::new(ogg_opus(&[b"abc", b"de"])))
+            .await
+            .unwrap();
+        match media {
+            DecodedMedia::Opus(frames) => assert_eq!(b"\x03\x00abc\x02\x00de"[..], *frames),
+            DecodedMedia::Pcm(_) => panic!("Ogg Opus is decoded to PCM"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decode_wav_stream() {
+        let media = decode_media_stream(wav(1)).await.unwrap();
+        let mut pcm = vec![];
+        decode(wav(1)).unwrap().read_to_end(&mut pcm).unwrap();
+        match media {
+            DecodedMedia::Pcm(samples) => assert_eq!(pcm[..], *samples),
+            DecodedMedia::Opus(_) => panic!("WAV is taken for Ogg Opus"),
+        }
+    }
+}
@@ -4,10 +4,12 @@ use serenity::client::Client;
 use serenity::framework::StandardFramework;
 use serenity::model::gateway::GatewayIntents;
 use songbird::SerenityInit;
+use std::sync::Arc;
 
 use huahua_discord::bot::Handler;
 use huahua_discord::config;
 use huahua_discord::music::MUSIC_GROUP;
+use huahua_discord::tools::Toolchain;
 
 #[tokio::main]
 async fn main() {
@@ -20,14 +22,26 @@ async fn main() {
         .await
         .expect("initializing mongodb client");
 
+    let toolchain = Toolchain::detect(&bot_config.tools)
+        .await
+        .expect("fail to find the external tools");
     let database = mongo_client.database("huahua");
+    let handler = Handler::new(
+        database,
+        toolchain.clone(),
+        bot_config.trim,
+        bot_config.hot_cache,
+        bot_config.queue,
+    );
+    handler.spawn_maintenance(bot_config.maintenance);
     let mut client = Client::builder(
         bot_config.token,
         GatewayIntents::non_privileged().union(GatewayIntents::MESSAGE_CONTENT),
     )
-    .event_handler(Handler::new(database))
+    .event_handler(handler)
     .application_id(bot_config.application_id)
     .framework(framework)
+    .type_map_insert::<Toolchain>(Arc::new(toolchain))
     .register_songbird()
     .await
     .expect("error while creating client");
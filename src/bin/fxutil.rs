@@ -1,6 +1,9 @@
 use clap::{Parser, Subcommand};
+use huahua_discord::config;
 use huahua_discord::fx::{self, Creator};
-use std::{fs, io, time};
+use huahua_discord::tools::Toolchain;
+use std::time;
+use tokio::fs;
 
 #[derive(Parser)]
 struct CreateOption {
@@ -10,6 +13,9 @@ struct CreateOption {
     start: u64,
     #[clap(short = 'l', default_value = "5")]
     length: u64,
+    /// effect chain, e.g. `tempo=1.25,pitch=-3,echo=300:40,fadeout=0.5`
+    #[clap(short = 'e', default_value = "")]
+    effects: String,
 }
 
 #[derive(Subcommand)]
@@ -27,18 +33,33 @@ struct Option {
 async fn main() {
     let option = Option::parse();
     match option.sub_commands {
-        SubCommands::Create(CreateOption { url, start, length }) => {
-            let mut out = fs::File::create("fxout.mp3").unwrap();
-            let creator = fx::YoutubeDLCreator;
+        SubCommands::Create(CreateOption {
+            url,
+            start,
+            length,
+            effects,
+        }) => {
+            let effects = fx::effect::parse_chain(&effects).expect("invalid effect chain");
+            let mut out = fs::File::create(format!("fxout.{}", fx::STORAGE_FORMAT.name()))
+                .await
+                .unwrap();
+            let tools = config::Tools::load()
+                .await
+                .expect("fail to load the tools config");
+            let toolchain = Toolchain::detect(&tools)
+                .await
+                .expect("fail to find the external tools");
+            let creator = fx::YoutubeDLCreator::new(toolchain);
             let mut result = creator
                 .create(&fx::MediaOrigin {
                     start: time::Duration::from_secs(start),
                     length: time::Duration::from_secs(length),
-                    url,
+                    source: fx::Source::Url { url },
+                    effects,
                 })
                 .await
                 .unwrap();
-            io::copy(&mut result, &mut out).unwrap();
+            tokio::io::copy(&mut result, &mut out).await.unwrap();
         }
     }
 }
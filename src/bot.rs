@@ -7,10 +7,13 @@ use serenity::{
 };
 
 use crate::{
+    config,
     fx::{
-        self, CachedCreator, Creator, LocalStore, MongoDBRepository, Repository, YoutubeDLCreator,
+        self, maintenance::CacheMaintenance, CachedCreator, Creator, LocalStore, MediaCreator,
+        MongoDBRepository, Repository,
     },
     interactions::{data::InteractionDataRegistry, fx::CreateFxCommand, ButtonHandler},
+    tools::Toolchain,
 };
 pub struct Handler<C, R>
 where
@@ -110,13 +113,28 @@ where
     }
 }
 
-impl Handler<CachedCreator<YoutubeDLCreator, LocalStore>, MongoDBRepository> {
-    pub fn new(database: mongodb::Database) -> Self {
+impl Handler<CachedCreator<MediaCreator<LocalStore>, LocalStore>, MongoDBRepository> {
+    pub fn new(
+        database: mongodb::Database,
+        tools: Toolchain,
+        trim: config::Trim,
+        hot_cache: config::HotCache,
+        queue: config::Queue,
+    ) -> Self {
         let store = fx::LocalStore::new("fx");
         let repository = fx::MongoDBRepository::new(database.clone());
+        let ffmpeg = tools.ffmpeg.clone();
         let controller = fx::Controller::new(
-            fx::CachedCreator::new(fx::YoutubeDLCreator, store),
+            fx::CachedCreator::new(
+                fx::MediaCreator::new(store.clone(), tools),
+                store,
+                ffmpeg.clone(),
+            ),
             repository,
+            ffmpeg,
+            trim,
+            hot_cache,
+            queue,
         );
         let interaction_data_registry = InteractionDataRegistry::new(database.clone());
         Self {
@@ -125,6 +143,16 @@ impl Handler<CachedCreator<YoutubeDLCreator, LocalStore>, MongoDBRepository> {
             interaction_data_registry,
         }
     }
+
+    pub fn spawn_maintenance(&self, config: config::Maintenance) {
+        let maintenance = CacheMaintenance::new(
+            self.controller.creator(),
+            self.controller.repository(),
+            self.controller.queue(),
+            config,
+        );
+        tokio::spawn(maintenance.run());
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -24,11 +24,160 @@ impl Database {
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Downloader {
+    YtDlp,
+    YoutubeDl,
+}
+
+/// Paths and extra arguments of the external programs.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Tools {
+    /// downloaders in the order of preference, the first one present on the host is used
+    pub downloaders: Vec<Downloader>,
+    pub yt_dlp: String,
+    pub yt_dlp_args: Vec<String>,
+    pub youtube_dl: String,
+    pub youtube_dl_args: Vec<String>,
+    pub ffmpeg: String,
+    pub ffmpeg_args: Vec<String>,
+    /// a download taking longer than this is killed
+    pub download_timeout_secs: u64,
+    /// an ffmpeg run taking longer than this is killed
+    pub ffmpeg_timeout_secs: u64,
+    /// text-to-speech engine compatible with espeak-ng, fx from text are disabled without it
+    pub speech: String,
+    pub speech_args: Vec<String>,
+    pub speech_timeout_secs: u64,
+}
+
+impl Default for Tools {
+    fn default() -> Self {
+        Self {
+            downloaders: vec![Downloader::YtDlp, Downloader::YoutubeDl],
+            yt_dlp: "yt-dlp".to_string(),
+            yt_dlp_args: vec![],
+            youtube_dl: "youtube-dl".to_string(),
+            youtube_dl_args: vec![],
+            ffmpeg: "ffmpeg".to_string(),
+            ffmpeg_args: vec![],
+            download_timeout_secs: 5 * 60,
+            ffmpeg_timeout_secs: 6 * 60,
+            speech: "espeak-ng".to_string(),
+            speech_args: vec![],
+            speech_timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ToolsOnly {
+    #[serde(default)]
+    tools: Tools,
+}
+
+impl Tools {
+    /// Load only the `[tools]` section of `./bot.toml`, defaults are used without the file.
+    pub async fn load() -> Result<Self, ConfigLoadError> {
+        match read_config().await {
+            Ok(buffer) => toml::from_slice::<ToolsOnly>(&buffer)
+                .map(|config| config.tools)
+                .map_err(ConfigLoadError::Format),
+            Err(ConfigLoadError::NoFound) => Ok(Self::default()),
+            Err(why) => Err(why),
+        }
+    }
+}
+
+/// Settings of the background cache maintenance, durations are in seconds.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Maintenance {
+    pub interval_secs: u64,
+    /// unreferenced cache entries younger than this are kept, e.g. previews not confirmed yet
+    pub grace_period_secs: u64,
+    pub prewarm_concurrency: usize,
+}
+
+impl Default for Maintenance {
+    fn default() -> Self {
+        Self {
+            interval_secs: 60 * 60,
+            grace_period_secs: 24 * 60 * 60,
+            prewarm_concurrency: 2,
+        }
+    }
+}
+
+/// Settings of trimming silence off new fx.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Trim {
+    /// quieter than this in dBFS counts as silence
+    pub silence_threshold_db: f32,
+}
+
+impl Default for Trim {
+    fn default() -> Self {
+        Self {
+            silence_threshold_db: -50.0,
+        }
+    }
+}
+
+/// Settings of keeping recently played fx decoded in memory.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct HotCache {
+    /// memory the decoded fx may take in total, in MiB
+    pub budget_mib: usize,
+}
+
+impl Default for HotCache {
+    fn default() -> Self {
+        Self { budget_mib: 64 }
+    }
+}
+
+/// Limits of rendering fx at the same time, rendering runs youtube-dl and ffmpeg.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Queue {
+    /// renders running at once in total
+    pub concurrency: usize,
+    /// renders running at once for one guild
+    pub guild_concurrency: usize,
+    /// renders one user may have waiting or running
+    pub user_pending: usize,
+}
+
+impl Default for Queue {
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            guild_concurrency: 2,
+            user_pending: 3,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Bot {
     pub token: String,
     pub application_id: u64,
     pub database: Database,
+    #[serde(default)]
+    pub maintenance: Maintenance,
+    #[serde(default)]
+    pub tools: Tools,
+    #[serde(default)]
+    pub trim: Trim,
+    #[serde(default)]
+    pub hot_cache: HotCache,
+    #[serde(default)]
+    pub queue: Queue,
 }
 
 #[derive(Debug)]
@@ -38,17 +187,22 @@ pub enum ConfigLoadError {
     IO(io::Error),
 }
 
+async fn read_config() -> Result<Vec<u8>, ConfigLoadError> {
+    let mut config = File::open("./bot.toml").await.map_err(|err| match err {
+        err if err.kind() == ErrorKind::NotFound => ConfigLoadError::NoFound,
+        err => ConfigLoadError::IO(err),
+    })?;
+    let mut buffer = vec![];
+    config
+        .read_to_end(&mut buffer)
+        .await
+        .map_err(ConfigLoadError::IO)?;
+    Ok(buffer)
+}
+
 impl Bot {
     pub async fn load() -> Result<Self, ConfigLoadError> {
-        let mut config = File::open("./bot.toml").await.map_err(|err| match err {
-            err if err.kind() == ErrorKind::NotFound => ConfigLoadError::NoFound,
-            err => ConfigLoadError::IO(err),
-        })?;
-        let mut buffer = vec![];
-        config
-            .read_to_end(&mut buffer)
-            .await
-            .map_err(ConfigLoadError::IO)?;
+        let buffer = read_config().await?;
         toml::from_slice(&buffer).map_err(ConfigLoadError::Format)
     }
 }
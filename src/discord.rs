@@ -2,12 +2,18 @@ use async_trait::async_trait;
 use serenity::{
     client::Context,
     model::{
-        application::interaction::application_command::ApplicationCommandInteraction,
+        application::interaction::{
+            application_command::ApplicationCommandInteraction,
+            message_component::MessageComponentInteraction,
+        },
         channel::Message,
+        guild::Member,
         id::{ChannelId, GuildId},
     },
 };
 
+use crate::i18n::Locale;
+
 pub fn check_serenity_result<T>(result: serenity::Result<T>) {
     if let Err(err) = result {
         log::error!("error sending message: {:?}", err);
@@ -47,23 +53,48 @@ pub trait AuthorVoiceChannelFinder {
     ) -> Result<Option<(GuildId, ChannelId)>, serenity::Error>;
 }
 
+/// The voice channel `member` of an interaction is in.
+fn member_voice_channel(
+    ctx: &Context,
+    guild_id: Option<GuildId>,
+    member: Option<&Member>,
+) -> Option<(GuildId, ChannelId)> {
+    let member = member?;
+    let guild_id = guild_id?;
+    ctx.cache.guild(guild_id).and_then(|guild| {
+        guild
+            .voice_states
+            .get(&member.user.id)
+            .and_then(|state| state.channel_id)
+            .map(|channel_id| (guild_id, channel_id))
+    })
+}
+
 #[async_trait]
 impl<'a> AuthorVoiceChannelFinder for InteractionWrapper<'a> {
     async fn find_user_voice_channel(
         &self,
     ) -> Result<Option<(GuildId, ChannelId)>, serenity::Error> {
-        if let Some(member) = &self.1.member {
-            if let Some(guild_id) = self.1.guild_id {
-                if let Some(guild) = self.0.cache.guild(guild_id) {
-                    return Ok(guild
-                        .voice_states
-                        .get(&member.user.id)
-                        .and_then(|state| state.channel_id)
-                        .map(|channel_id| (guild_id, channel_id)));
-                }
-            }
-        }
-        Ok(None)
+        Ok(member_voice_channel(
+            self.0,
+            self.1.guild_id,
+            self.1.member.as_ref(),
+        ))
+    }
+}
+
+pub struct ComponentWrapper<'a>(pub &'a Context, pub &'a MessageComponentInteraction);
+
+#[async_trait]
+impl<'a> AuthorVoiceChannelFinder for ComponentWrapper<'a> {
+    async fn find_user_voice_channel(
+        &self,
+    ) -> Result<Option<(GuildId, ChannelId)>, serenity::Error> {
+        Ok(member_voice_channel(
+            self.0,
+            self.1.guild_id,
+            self.1.member.as_ref(),
+        ))
     }
 }
 
@@ -81,3 +112,31 @@ impl<'a> AuthorVoiceChannelFinder for MessageWrapper<'a> {
         }))
     }
 }
+
+/// Whoever the bot answers, speaking their language.
+pub trait Localized {
+    fn locale(&self) -> Locale;
+}
+
+impl<'a> Localized for MessageWrapper<'a> {
+    /// Messages don't tell the locale of their author, so the guild's is used.
+    fn locale(&self) -> Locale {
+        let guild = self.1.guild(&self.0.cache);
+        Locale::choose(
+            None,
+            guild.as_ref().map(|guild| guild.preferred_locale.as_str()),
+        )
+    }
+}
+
+impl<'a> Localized for InteractionWrapper<'a> {
+    fn locale(&self) -> Locale {
+        Locale::choose(Some(&self.1.locale), self.1.guild_locale.as_deref())
+    }
+}
+
+impl<'a> Localized for ComponentWrapper<'a> {
+    fn locale(&self) -> Locale {
+        Locale::choose(Some(&self.1.locale), self.1.guild_locale.as_deref())
+    }
+}
@@ -0,0 +1,328 @@
+use std::fmt::Debug;
+use std::time::Duration;
+
+use songbird::{error::JoinError, tracks::TrackError};
+
+use crate::audio::PlayError;
+use crate::fx::{
+    queue::QueueError, AttachmentCreateError, CachedCreatorError, ComposeError, GetFxError,
+    MediaCreateError, PastEndError, RenderError, RepositoryAddError, RepositoryGetError,
+    SpeechCreateError, StoreGetError, UploadError, YoutubeDLCreateError,
+};
+use crate::i18n::Locale;
+
+/// What went wrong, in the terms the user is told.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reason {
+    FxNotFound,
+    FxExists,
+    /// the cut ends after the source, which is this long
+    PastEnd(Duration),
+    /// the segments of a combo are written wrong
+    Composite(String),
+    /// an fx in the segments of a combo doesn't exist
+    PartNotFound(String),
+    UserNotInVoice,
+    BotNotInVoice,
+    CannotJoin,
+    NotPlaying,
+    NotSeekable,
+    CannotPlay,
+    Undecodable,
+    TooManyPending,
+    /// the source couldn't be downloaded or read
+    SourceUnavailable,
+    UnsupportedUpload,
+    UploadGone,
+    SpeechUnavailable,
+    /// the media couldn't be cut or converted
+    Render,
+    /// the interaction refers to data that's gone
+    Stale,
+    /// failures on our side, e.g. of the database or Discord
+    Internal,
+}
+
+impl Reason {
+    /// Whether it's a failure of the bot rather than something the user can fix.
+    fn is_fault(&self) -> bool {
+        matches!(
+            self,
+            Reason::CannotPlay
+                | Reason::Undecodable
+                | Reason::SpeechUnavailable
+                | Reason::Render
+                | Reason::Internal
+        )
+    }
+
+    fn text(&self, locale: Locale) -> String {
+        match self {
+            Reason::FxNotFound => locale.text("error-fx-not-found"),
+            Reason::FxExists => locale.text("error-fx-exists"),
+            Reason::PastEnd(duration) => {
+                let seconds = format!("{:.1}", duration.as_secs_f64());
+                locale.text_with("error-past-end", &[("seconds", &seconds)])
+            }
+            Reason::Composite(why) => locale.text_with("error-composite", &[("why", why)]),
+            Reason::PartNotFound(name) => {
+                locale.text_with("error-part-not-found", &[("name", name)])
+            }
+            Reason::UserNotInVoice => locale.text("error-user-not-in-voice"),
+            Reason::BotNotInVoice => locale.text("error-bot-not-in-voice"),
+            Reason::CannotJoin => locale.text("error-cannot-join"),
+            Reason::NotPlaying => locale.text("error-not-playing"),
+            Reason::NotSeekable => locale.text("error-not-seekable"),
+            Reason::CannotPlay => locale.text("error-cannot-play"),
+            Reason::Undecodable => locale.text("error-undecodable"),
+            Reason::TooManyPending => locale.text("error-too-many-pending"),
+            Reason::SourceUnavailable => locale.text("error-source-unavailable"),
+            Reason::UnsupportedUpload => locale.text("error-unsupported-upload"),
+            Reason::UploadGone => locale.text("error-upload-gone"),
+            Reason::SpeechUnavailable => locale.text("error-speech-unavailable"),
+            Reason::Render => locale.text("error-render"),
+            Reason::Stale => locale.text("error-stale"),
+            Reason::Internal => locale.text("error-internal"),
+        }
+    }
+}
+
+/// Errors that can be told to users.
+pub trait UserFacing: Debug {
+    fn reason(&self) -> Reason;
+}
+
+/// A failure as the user is told about it. The correlation id is shown to the user and logged
+/// along with the cause, so reports can be matched with the log.
+#[derive(Debug)]
+pub struct UserError {
+    reason: Reason,
+    id: String,
+}
+
+impl UserError {
+    /// Tell the user `reason`, logging `cause` under a new correlation id.
+    pub fn new(reason: Reason, cause: &dyn Debug) -> Self {
+        let id = format!("{:08x}", rand::random::<u32>());
+        if reason.is_fault() {
+            log::error!("[{}] {:?}", id, cause);
+        } else {
+            log::info!("[{}] {:?}", id, cause);
+        }
+        Self { reason, id }
+    }
+
+    pub fn reason(&self) -> &Reason {
+        &self.reason
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// What the user is told, in `locale`.
+    pub fn message(&self, locale: Locale) -> String {
+        let reason = self.reason.text(locale);
+        locale.text_with("error", &[("reason", &reason), ("id", &self.id)])
+    }
+}
+
+impl<E: UserFacing> From<E> for UserError {
+    fn from(why: E) -> Self {
+        Self::new(why.reason(), &why)
+    }
+}
+
+impl UserFacing for serenity::Error {
+    fn reason(&self) -> Reason {
+        Reason::Internal
+    }
+}
+
+impl UserFacing for mongodb::error::Error {
+    fn reason(&self) -> Reason {
+        Reason::Internal
+    }
+}
+
+impl UserFacing for JoinError {
+    fn reason(&self) -> Reason {
+        Reason::CannotJoin
+    }
+}
+
+impl UserFacing for TrackError {
+    fn reason(&self) -> Reason {
+        match self {
+            TrackError::SeekUnsupported => Reason::NotSeekable,
+            _ => Reason::NotPlaying,
+        }
+    }
+}
+
+impl UserFacing for PlayError {
+    fn reason(&self) -> Reason {
+        match self {
+            PlayError::NotInChannel => Reason::BotNotInVoice,
+            PlayError::CannotPlay => Reason::CannotPlay,
+            PlayError::Undecodable => Reason::Undecodable,
+        }
+    }
+}
+
+impl UserFacing for RepositoryAddError {
+    fn reason(&self) -> Reason {
+        match self {
+            RepositoryAddError::AlreadyExists => Reason::FxExists,
+            RepositoryAddError::IO(_) => Reason::Internal,
+        }
+    }
+}
+
+impl UserFacing for RepositoryGetError {
+    fn reason(&self) -> Reason {
+        match self {
+            RepositoryGetError::NotFound => Reason::FxNotFound,
+            RepositoryGetError::IO(_) => Reason::Internal,
+        }
+    }
+}
+
+impl UserFacing for StoreGetError {
+    fn reason(&self) -> Reason {
+        Reason::Internal
+    }
+}
+
+impl UserFacing for UploadError {
+    fn reason(&self) -> Reason {
+        match self {
+            UploadError::Unsupported => Reason::UnsupportedUpload,
+            UploadError::Store(_) => Reason::Internal,
+        }
+    }
+}
+
+impl UserFacing for YoutubeDLCreateError {
+    fn reason(&self) -> Reason {
+        match self {
+            YoutubeDLCreateError::Stage(_) => Reason::SourceUnavailable,
+            _ => Reason::Internal,
+        }
+    }
+}
+
+impl UserFacing for AttachmentCreateError {
+    fn reason(&self) -> Reason {
+        match self {
+            AttachmentCreateError::Upload(StoreGetError::NotFound) => Reason::UploadGone,
+            AttachmentCreateError::Stage(_) => Reason::Undecodable,
+            _ => Reason::Internal,
+        }
+    }
+}
+
+impl UserFacing for SpeechCreateError {
+    fn reason(&self) -> Reason {
+        match self {
+            SpeechCreateError::Unavailable => Reason::SpeechUnavailable,
+            SpeechCreateError::Stage(_) => Reason::Render,
+            _ => Reason::Internal,
+        }
+    }
+}
+
+impl UserFacing for MediaCreateError {
+    fn reason(&self) -> Reason {
+        match self {
+            MediaCreateError::YoutubeDL(why) => why.reason(),
+            MediaCreateError::Attachment(why) => why.reason(),
+            MediaCreateError::Speech(why) => why.reason(),
+            MediaCreateError::Part(why) => why.reason(),
+            MediaCreateError::Decode(_) | MediaCreateError::Stage(_) => Reason::Render,
+            MediaCreateError::FFmpeg(_) => Reason::Internal,
+        }
+    }
+}
+
+impl<S: Debug, C: UserFacing> UserFacing for CachedCreatorError<S, C> {
+    fn reason(&self) -> Reason {
+        match self {
+            CachedCreatorError::Cache(_) => Reason::Internal,
+            CachedCreatorError::Create(why) => why.reason(),
+        }
+    }
+}
+
+impl UserFacing for QueueError {
+    fn reason(&self) -> Reason {
+        match self {
+            QueueError::TooManyPending => Reason::TooManyPending,
+        }
+    }
+}
+
+impl<C: UserFacing> UserFacing for GetFxError<C> {
+    fn reason(&self) -> Reason {
+        match self {
+            GetFxError::Repository(why) => why.reason(),
+            GetFxError::Create(why) => why.reason(),
+            GetFxError::Queue(why) => why.reason(),
+        }
+    }
+}
+
+impl<C: UserFacing> UserFacing for RenderError<C> {
+    fn reason(&self) -> Reason {
+        match self {
+            RenderError::Create(why) => why.reason(),
+            RenderError::Queue(why) => why.reason(),
+            RenderError::Export(_) | RenderError::Media(_) => Reason::Render,
+        }
+    }
+}
+
+impl UserFacing for PastEndError {
+    fn reason(&self) -> Reason {
+        Reason::PastEnd(self.duration)
+    }
+}
+
+impl UserFacing for ComposeError {
+    fn reason(&self) -> Reason {
+        match self {
+            ComposeError::Parse(why) => Reason::Composite(why.to_string()),
+            ComposeError::FxNotFound(name) => Reason::PartNotFound(name.clone()),
+            ComposeError::Repository(why) => why.reason(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case(GetFxError::Repository(RepositoryGetError::NotFound) => Reason::FxNotFound; "not found")]
+    #[test_case(GetFxError::Queue(QueueError::TooManyPending) => Reason::TooManyPending; "queue")]
+    #[test_case(GetFxError::Create(CachedCreatorError::Create(MediaCreateError::Speech(SpeechCreateError::Unavailable))) => Reason::SpeechUnavailable; "creator")]
+    #[test_case(GetFxError::Create(CachedCreatorError::Cache(io::Error::from(io::ErrorKind::Other))) => Reason::Internal; "cache")]
+    fn test_reason(why: GetFxError<CachedCreatorError<io::Error, MediaCreateError>>) -> Reason {
+        why.reason()
+    }
+
+    #[test]
+    fn test_message_shows_id() {
+        let error = UserError::from(PlayError::NotInChannel);
+        assert_eq!(&Reason::BotNotInVoice, error.reason());
+        assert_eq!(8, error.id().len());
+        assert!(error.message(Locale::EnUs).contains(error.id()));
+        assert_eq!(
+            format!("Mew... Not in a voice channel (error code: {})", error.id()),
+            error.message(Locale::EnUs)
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    time::Duration,
+};
+
+use super::{MediaOrigin, Source};
+
+/// Parts are joined as interleaved 16-bit stereo at this rate.
+pub const SAMPLE_RATE: u32 = 48000;
+pub const CHANNELS: u32 = 2;
+pub const MAX_SEGMENTS: usize = 8;
+const MAX_JOIN_MILLIS: u32 = 5000;
+
+/// How a segment is joined to the one before it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Join {
+    Cut,
+    Gap { millis: u32 },
+    Crossfade { millis: u32 },
+}
+
+impl Default for Join {
+    fn default() -> Self {
+        Join::Cut
+    }
+}
+
+impl Display for Join {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Join::Cut => write!(f, "cut"),
+            Join::Gap { millis } => write!(f, "gap={}", millis),
+            Join::Crossfade { millis } => write!(f, "xfade={}", millis),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Segment {
+    pub origin: MediaOrigin,
+    /// name of the fx the origin was taken from, the origin is a copy so later edits of that fx
+    /// don't change the composite
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fx: Option<String>,
+    #[serde(default)]
+    pub join: Join,
+}
+
+impl Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.fx {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{}", self.origin.source),
+        }
+    }
+}
+
+/// A part of a composite as written by a user, fx are referred by name until they are resolved.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PartSpec {
+    Fx(String),
+    Url {
+        url: String,
+        start: Duration,
+        length: Duration,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SegmentSpec {
+    pub part: PartSpec,
+    pub join: Join,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CompositeParseError {
+    Empty,
+    TooManySegments,
+    InvalidJoin(String),
+    InvalidPart(String),
+    /// joins must be between two parts
+    DanglingJoin,
+}
+
+impl Display for CompositeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompositeParseError::Empty => write!(f, "no segment"),
+            CompositeParseError::TooManySegments => {
+                write!(f, "at most {} segments", MAX_SEGMENTS)
+            }
+            CompositeParseError::InvalidJoin(token) => write!(f, "invalid join `{}`", token),
+            CompositeParseError::InvalidPart(token) => write!(f, "invalid segment `{}`", token),
+            CompositeParseError::DanglingJoin => write!(f, "a join must be between two segments"),
+        }
+    }
+}
+
+fn parse_millis(token: &str, secs: &str) -> Result<u32, CompositeParseError> {
+    let secs: f64 = secs
+        .parse()
+        .map_err(|_| CompositeParseError::InvalidJoin(token.to_string()))?;
+    let millis = (secs * 1000.0).round();
+    if !millis.is_finite() || millis < 1.0 || millis > MAX_JOIN_MILLIS as f64 {
+        return Err(CompositeParseError::InvalidJoin(token.to_string()));
+    }
+    Ok(millis as u32)
+}
+
+fn parse_secs(token: &str, secs: &str) -> Result<Duration, CompositeParseError> {
+    secs.parse()
+        .map(Duration::from_secs)
+        .map_err(|_| CompositeParseError::InvalidPart(token.to_string()))
+}
+
+/// `<url>[@start][+length]`, in whole seconds like `/fx create`.
+fn parse_url(token: &str) -> Result<PartSpec, CompositeParseError> {
+    let (rest, length) = match token.rsplit_once('+') {
+        Some((rest, length)) => (rest, parse_secs(token, length)?),
+        None => (token, Duration::from_secs(5)),
+    };
+    let (url, start) = match rest.rsplit_once('@') {
+        Some((url, start)) => (url, parse_secs(token, start)?),
+        None => (rest, Duration::ZERO),
+    };
+    if length.is_zero() || length > Duration::from_secs(20) {
+        return Err(CompositeParseError::InvalidPart(token.to_string()));
+    }
+    Ok(PartSpec::Url {
+        url: url.to_string(),
+        start,
+        length,
+    })
+}
+
+/// Parse segments separated by commas, e.g. `meow, gap=0.2, https://youtu.be/x@3+2, xfade=0.3,
+/// purr`. Parts are names of fx or links, `gap` and `xfade` are in seconds and join the parts
+/// around them.
+pub fn parse_segments(spec: &str) -> Result<Vec<SegmentSpec>, CompositeParseError> {
+    let mut segments = vec![];
+    let mut join = None;
+    for token in spec
+        .split(',')
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+    {
+        if let Some(secs) = token.strip_prefix("gap=") {
+            if segments.is_empty() || join.is_some() {
+                return Err(CompositeParseError::DanglingJoin);
+            }
+            join = Some(Join::Gap {
+                millis: parse_millis(token, secs)?,
+            });
+            continue;
+        }
+        if let Some(secs) = token.strip_prefix("xfade=") {
+            if segments.is_empty() || join.is_some() {
+                return Err(CompositeParseError::DanglingJoin);
+            }
+            join = Some(Join::Crossfade {
+                millis: parse_millis(token, secs)?,
+            });
+            continue;
+        }
+        let part = if token.starts_with("http://") || token.starts_with("https://") {
+            parse_url(token)?
+        } else {
+            PartSpec::Fx(token.to_string())
+        };
+        segments.push(SegmentSpec {
+            part,
+            join: join.take().unwrap_or_default(),
+        });
+    }
+    if join.is_some() {
+        return Err(CompositeParseError::DanglingJoin);
+    }
+    if segments.is_empty() {
+        return Err(CompositeParseError::Empty);
+    }
+    if segments.len() > MAX_SEGMENTS {
+        return Err(CompositeParseError::TooManySegments);
+    }
+    Ok(segments)
+}
+
+impl PartSpec {
+    /// The origin of a link part, fx parts must be resolved by name instead.
+    pub fn to_origin(&self) -> Option<MediaOrigin> {
+        match self {
+            PartSpec::Fx(_) => None,
+            PartSpec::Url { url, start, length } => Some(MediaOrigin {
+                source: Source::Url { url: url.clone() },
+                start: *start,
+                length: *length,
+                effects: vec![],
+            }),
+        }
+    }
+}
+
+fn samples(millis: u32) -> usize {
+    (millis as u64 * SAMPLE_RATE as u64 / 1000 * CHANNELS as u64) as usize
+}
+
+/// Append the samples of the next part to `joined`.
+pub fn join(joined: &mut Vec<i16>, next: &[i16], join: &Join) {
+    match join {
+        Join::Cut => joined.extend_from_slice(next),
+        Join::Gap { millis } => {
+            joined.resize(joined.len() + samples(*millis), 0);
+            joined.extend_from_slice(next);
+        }
+        Join::Crossfade { millis } => {
+            // whole frames only, so channels stay aligned
+            let overlap = samples(*millis).min(joined.len()).min(next.len());
+            let overlap = overlap - overlap % CHANNELS as usize;
+            let start = joined.len() - overlap;
+            for (i, sample) in next[..overlap].iter().enumerate() {
+                let frame = (i / CHANNELS as usize) as f32;
+                let frames = (overlap / CHANNELS as usize).max(1) as f32;
+                let fade_in = frame / frames;
+                let mixed = joined[start + i] as f32 * (1.0 - fade_in) + *sample as f32 * fade_in;
+                joined[start + i] = mixed.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+            }
+            joined.extend_from_slice(&next[overlap..]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{join, parse_segments, CompositeParseError, Join, PartSpec, SegmentSpec};
+
+    #[test]
+    fn test_parse_segments() {
+        assert_eq!(
+            Ok(vec![
+                SegmentSpec {
+                    part: PartSpec::Fx("meow".to_string()),
+                    join: Join::Cut,
+                },
+                SegmentSpec {
+                    part: PartSpec::Url {
+                        url: "https://youtu.be/x".to_string(),
+                        start: Duration::from_secs(3),
+                        length: Duration::from_secs(2),
+                    },
+                    join: Join::Gap { millis: 200 },
+                },
+                SegmentSpec {
+                    part: PartSpec::Fx("purr".to_string()),
+                    join: Join::Crossfade { millis: 300 },
+                },
+            ]),
+            parse_segments("meow, gap=0.2, https://youtu.be/x@3+2, xfade=0.3, purr")
+        );
+    }
+
+    #[test]
+    fn test_parse_dangling_join() {
+        assert_eq!(
+            Err(CompositeParseError::DanglingJoin),
+            parse_segments("meow, gap=0.2")
+        );
+        assert_eq!(
+            Err(CompositeParseError::DanglingJoin),
+            parse_segments("gap=0.2, meow")
+        );
+    }
+
+    #[test]
+    fn test_join_with_gap() {
+        let mut joined = vec![1, 1];
+        join(&mut joined, &[2, 2], &Join::Gap { millis: 1 });
+        assert_eq!(2 + 96 + 2, joined.len());
+        assert_eq!(&[2, 2], &joined[joined.len() - 2..]);
+    }
+
+    #[test]
+    fn test_join_with_crossfade_overlaps() {
+        let mut joined = vec![100; 960];
+        join(&mut joined, &[0; 960], &Join::Crossfade { millis: 5 });
+        assert_eq!(960 + 480, joined.len());
+        assert_eq!(100, joined[480]);
+        assert!(joined[900] < 100);
+    }
+}
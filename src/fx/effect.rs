@@ -0,0 +1,239 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+/// Sample rate the filter graph works at, so pitch shifting knows the rate it changes.
+const FILTER_SAMPLE_RATE: u32 = 48000;
+
+/// Audio processing applied to an fx after it's cut, in the order of the chain. Values are
+/// integers so a chain always encodes to the same cache key.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Effect {
+    FadeIn {
+        millis: u32,
+    },
+    FadeOut {
+        millis: u32,
+    },
+    /// speed in percent of the original, without changing the pitch
+    Tempo {
+        percent: u32,
+    },
+    /// semitones up or down, without changing the speed
+    Pitch {
+        semitones: i32,
+    },
+    Reverse,
+    Echo {
+        delay_millis: u32,
+        decay_percent: u32,
+    },
+    BassBoost {
+        gain_db: i32,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum EffectParseError {
+    Unknown(String),
+    MissingValue(String),
+    InvalidValue(String),
+    OutOfRange(String),
+}
+
+impl Display for EffectParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EffectParseError::Unknown(effect) => write!(f, "unknown effect `{}`", effect),
+            EffectParseError::MissingValue(effect) => write!(f, "`{}` needs a value", effect),
+            EffectParseError::InvalidValue(effect) => write!(f, "invalid value of `{}`", effect),
+            EffectParseError::OutOfRange(effect) => write!(f, "`{}` is out of range", effect),
+        }
+    }
+}
+
+impl std::error::Error for EffectParseError {}
+
+fn seconds(millis: u32) -> String {
+    format!("{}.{:03}", millis / 1000, millis % 1000)
+}
+
+impl Display for Effect {
+    /// The compact syntax accepted by [`parse_chain`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Effect::FadeIn { millis } => write!(f, "fadein={}", seconds(*millis)),
+            Effect::FadeOut { millis } => write!(f, "fadeout={}", seconds(*millis)),
+            Effect::Tempo { percent } => write!(f, "tempo={}.{:02}", percent / 100, percent % 100),
+            Effect::Pitch { semitones } => write!(f, "pitch={}", semitones),
+            Effect::Reverse => write!(f, "reverse"),
+            Effect::Echo {
+                delay_millis,
+                decay_percent,
+            } => write!(f, "echo={}:{}", delay_millis, decay_percent),
+            Effect::BassBoost { gain_db } => write!(f, "bass={}", gain_db),
+        }
+    }
+}
+
+impl Effect {
+    pub fn validate(&self) -> Result<(), EffectParseError> {
+        let in_range = match self {
+            Effect::FadeIn { millis } | Effect::FadeOut { millis } => (1..=20_000).contains(millis),
+            // a single atempo filter only handles this range
+            Effect::Tempo { percent } => (50..=200).contains(percent),
+            Effect::Pitch { semitones } => (-12..=12).contains(semitones),
+            Effect::Reverse => true,
+            Effect::Echo {
+                delay_millis,
+                decay_percent,
+            } => (1..=2000).contains(delay_millis) && (1..=100).contains(decay_percent),
+            Effect::BassBoost { gain_db } => (-20..=20).contains(gain_db),
+        };
+        if in_range {
+            Ok(())
+        } else {
+            Err(EffectParseError::OutOfRange(self.to_string()))
+        }
+    }
+
+    fn filter(&self) -> String {
+        match self {
+            Effect::FadeIn { millis } => format!("afade=t=in:st=0:d={}", seconds(*millis)),
+            // the length of the clip isn't known ahead, so fade in the reversed clip instead
+            Effect::FadeOut { millis } => {
+                format!("areverse,afade=t=in:st=0:d={},areverse", seconds(*millis))
+            }
+            Effect::Tempo { percent } => format!("atempo={}", *percent as f64 / 100.0),
+            Effect::Pitch { semitones } => {
+                let ratio = 2f64.powf(*semitones as f64 / 12.0);
+                format!(
+                    "asetrate={},aresample={},atempo={}",
+                    (FILTER_SAMPLE_RATE as f64 * ratio).round(),
+                    FILTER_SAMPLE_RATE,
+                    1.0 / ratio
+                )
+            }
+            Effect::Reverse => "areverse".to_string(),
+            Effect::Echo {
+                delay_millis,
+                decay_percent,
+            } => format!(
+                "aecho=0.8:0.9:{}:{}",
+                delay_millis,
+                *decay_percent as f64 / 100.0
+            ),
+            Effect::BassBoost { gain_db } => format!("bass=g={}", gain_db),
+        }
+    }
+}
+
+fn parse_millis(name: &str, value: &str) -> Result<u32, EffectParseError> {
+    let secs: f64 = value
+        .parse()
+        .map_err(|_| EffectParseError::InvalidValue(name.to_string()))?;
+    if !secs.is_finite() || secs < 0.0 {
+        return Err(EffectParseError::InvalidValue(name.to_string()));
+    }
+    Ok((secs * 1000.0).round() as u32)
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, EffectParseError> {
+    value
+        .parse()
+        .map_err(|_| EffectParseError::InvalidValue(name.to_string()))
+}
+
+fn parse_effect(effect: &str) -> Result<Effect, EffectParseError> {
+    let (name, value) = match effect.split_once('=') {
+        Some((name, value)) => (name.trim(), Some(value.trim())),
+        None => (effect.trim(), None),
+    };
+    let value = || value.ok_or_else(|| EffectParseError::MissingValue(name.to_string()));
+    let effect = match name {
+        "fadein" => Effect::FadeIn {
+            millis: parse_millis(name, value()?)?,
+        },
+        "fadeout" => Effect::FadeOut {
+            millis: parse_millis(name, value()?)?,
+        },
+        "tempo" => {
+            let factor: f64 = parse_value(name, value()?)?;
+            if !factor.is_finite() || factor < 0.0 {
+                return Err(EffectParseError::InvalidValue(name.to_string()));
+            }
+            Effect::Tempo {
+                percent: (factor * 100.0).round() as u32,
+            }
+        }
+        "pitch" => Effect::Pitch {
+            semitones: parse_value(name, value()?)?,
+        },
+        "reverse" => Effect::Reverse,
+        "echo" => {
+            let (delay, decay) = value()?
+                .split_once(':')
+                .ok_or_else(|| EffectParseError::InvalidValue(name.to_string()))?;
+            Effect::Echo {
+                delay_millis: parse_value(name, delay)?,
+                decay_percent: parse_value(name, decay)?,
+            }
+        }
+        "bass" => Effect::BassBoost {
+            gain_db: parse_value(name, value()?)?,
+        },
+        name => return Err(EffectParseError::Unknown(name.to_string())),
+    };
+    effect.validate()?;
+    Ok(effect)
+}
+
+/// Parse the compact syntax of a chain, e.g. `tempo=1.25,pitch=-3,echo=300:40,fadeout=0.5`.
+/// Fades are in seconds, tempo is a factor, echo is the delay in milliseconds and the decay in
+/// percent, bass is the gain in dB.
+pub fn parse_chain(chain: &str) -> Result<Vec<Effect>, EffectParseError> {
+    chain
+        .split(',')
+        .filter(|effect| !effect.trim().is_empty())
+        .map(parse_effect)
+        .collect()
+}
+
+/// The ffmpeg filter graph applying `effects` in order, nothing if there is no effect.
+pub fn filter_graph(effects: &[Effect]) -> Option<String> {
+    if effects.is_empty() {
+        return None;
+    }
+    let filters: Vec<String> = effects.iter().map(Effect::filter).collect();
+    Some(format!(
+        "aresample={},{}",
+        FILTER_SAMPLE_RATE,
+        filters.join(",")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::{parse_chain, Effect, EffectParseError};
+
+    #[test_case("" => Ok(vec![]); "empty")]
+    #[test_case("reverse" => Ok(vec![Effect::Reverse]); "reverse")]
+    #[test_case("tempo=1.25, pitch=-3" => Ok(vec![Effect::Tempo { percent: 125 }, Effect::Pitch { semitones: -3 }]); "tempo and pitch")]
+    #[test_case("echo=300:40,fadeout=0.5" => Ok(vec![Effect::Echo { delay_millis: 300, decay_percent: 40 }, Effect::FadeOut { millis: 500 }]); "echo and fade")]
+    #[test_case("bass" => Err(EffectParseError::MissingValue("bass".to_string())); "missing value")]
+    #[test_case("tempo=3" => Err(EffectParseError::OutOfRange("tempo=3.00".to_string())); "out of range")]
+    #[test_case("wobble=1" => Err(EffectParseError::Unknown("wobble".to_string())); "unknown")]
+    fn test_parse_chain(chain: &str) -> Result<Vec<Effect>, EffectParseError> {
+        parse_chain(chain)
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let chain =
+            parse_chain("fadein=0.25,tempo=0.8,pitch=5,reverse,echo=120:60,bass=6").unwrap();
+        let compact: Vec<String> = chain.iter().map(Effect::to_string).collect();
+        assert_eq!(chain, parse_chain(&compact.join(",")).unwrap());
+    }
+}
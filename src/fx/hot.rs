@@ -0,0 +1,156 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::audio::DecodedMedia;
+
+use super::FxIdentity;
+
+struct HotEntry {
+    /// cache key of the media the fx had when it was decoded
+    key: String,
+    media: DecodedMedia,
+    last_played: u64,
+}
+
+/// Recently played fx decoded in memory, so playing them again starts at once. The fx played
+/// least recently are evicted to stay within the budget.
+pub struct HotCache {
+    budget: usize,
+    size: usize,
+    /// ticks on every play, orders the entries by when they were last played
+    clock: u64,
+    entries: HashMap<FxIdentity, HotEntry>,
+    /// fx being decoded, so a burst of plays decodes each once
+    warming: HashSet<FxIdentity>,
+}
+
+impl HotCache {
+    /// `budget` is in bytes.
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            size: 0,
+            clock: 0,
+            entries: HashMap::new(),
+            warming: HashSet::new(),
+        }
+    }
+
+    /// The decoded media of `identity` if it's still of the media with cache key `key`, entries
+    /// of media that has changed since are dropped.
+    pub fn get(&mut self, identity: &FxIdentity, key: &str) -> Option<DecodedMedia> {
+        self.clock += 1;
+        match self.entries.get_mut(identity) {
+            Some(entry) if entry.key == key => {
+                entry.last_played = self.clock;
+                return Some(entry.media.clone());
+            }
+            Some(_) => (),
+            None => return None,
+        }
+        self.invalidate(identity);
+        None
+    }
+
+    /// Whether `identity` should be decoded, false if it's being decoded already.
+    pub fn start_warming(&mut self, identity: &FxIdentity) -> bool {
+        self.warming.insert(identity.clone())
+    }
+
+    pub fn stop_warming(&mut self, identity: &FxIdentity) {
+        self.warming.remove(identity);
+    }
+
+    pub fn insert(&mut self, identity: FxIdentity, key: String, media: DecodedMedia) {
+        self.stop_warming(&identity);
+        self.invalidate(&identity);
+        let size = media.size();
+        if size > self.budget {
+            return;
+        }
+        while self.size + size > self.budget {
+            let coldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_played)
+                .map(|(identity, _)| identity.clone());
+            match coldest {
+                Some(coldest) => self.invalidate(&coldest),
+                None => break,
+            }
+        }
+        self.clock += 1;
+        self.size += size;
+        self.entries.insert(
+            identity,
+            HotEntry {
+                key,
+                media,
+                last_played: self.clock,
+            },
+        );
+    }
+
+    pub fn invalidate(&mut self, identity: &FxIdentity) {
+        if let Some(entry) = self.entries.remove(identity) {
+            self.size -= entry.media.size();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serenity::model::id::GuildId;
+
+    use super::*;
+
+    fn identity(name: &str) -> FxIdentity {
+        FxIdentity(GuildId(1), name.to_string())
+    }
+
+    fn media(size: usize) -> DecodedMedia {
+        DecodedMedia::Pcm(Arc::from(vec![0; size]))
+    }
+
+    #[test]
+    fn test_least_recently_played_is_evicted() {
+        let mut cache = HotCache::new(300);
+        cache.insert(identity("a"), "a".to_string(), media(100));
+        cache.insert(identity("b"), "b".to_string(), media(100));
+        cache.insert(identity("c"), "c".to_string(), media(100));
+        assert!(cache.get(&identity("a"), "a").is_some());
+        cache.insert(identity("d"), "d".to_string(), media(100));
+        assert!(cache.get(&identity("a"), "a").is_some());
+        assert!(cache.get(&identity("b"), "b").is_none());
+        assert!(cache.get(&identity("c"), "c").is_some());
+        assert!(cache.get(&identity("d"), "d").is_some());
+    }
+
+    #[test]
+    fn test_changed_media_is_dropped() {
+        let mut cache = HotCache::new(300);
+        cache.insert(identity("a"), "old".to_string(), media(100));
+        assert!(cache.get(&identity("a"), "new").is_none());
+        assert!(cache.get(&identity("a"), "old").is_none());
+        assert_eq!(0, cache.size);
+    }
+
+    #[test]
+    fn test_media_over_budget_is_not_kept() {
+        let mut cache = HotCache::new(300);
+        cache.insert(identity("a"), "a".to_string(), media(100));
+        cache.insert(identity("b"), "b".to_string(), media(301));
+        assert!(cache.get(&identity("a"), "a").is_some());
+        assert!(cache.get(&identity("b"), "b").is_none());
+    }
+
+    #[test]
+    fn test_warming_once() {
+        let mut cache = HotCache::new(300);
+        assert!(cache.start_warming(&identity("a")));
+        assert!(!cache.start_warming(&identity("a")));
+        cache.insert(identity("a"), "a".to_string(), media(100));
+        assert!(cache.start_warming(&identity("a")));
+    }
+}
@@ -0,0 +1,157 @@
+use std::{
+    collections::HashSet,
+    io,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use futures::StreamExt;
+
+use crate::config;
+
+use super::{
+    progress::ProgressReporter,
+    queue::{JobOwner, RenderQueue},
+    CachedCreator, Creator, MediaOrigin, Repository, Store,
+};
+
+/// Background upkeep of the media cache. Entries that no confirmed fx refers to are collected
+/// once they are older than the grace period, which leaves time to confirm previews. Confirmed fx
+/// missing from the cache are rendered, or migrated from entries of older formats, ahead of their
+/// next play. Those renders take their turns in the render queue along with the ones of users.
+pub struct CacheMaintenance<C, S, R>
+where
+    C: Creator,
+    S: Store,
+    R: Repository,
+{
+    creator: Arc<CachedCreator<C, S>>,
+    repository: Arc<R>,
+    queue: Arc<RenderQueue>,
+    config: config::Maintenance,
+}
+
+impl<C, S, R> CacheMaintenance<C, S, R>
+where
+    C: Creator + 'static,
+    S: Store + 'static,
+    R: Repository,
+    S::Output: Sync + Send + Unpin + 'static,
+    C::Output: Sync + Send + Unpin + 'static,
+{
+    pub fn new(
+        creator: Arc<CachedCreator<C, S>>,
+        repository: Arc<R>,
+        queue: Arc<RenderQueue>,
+        config: config::Maintenance,
+    ) -> Self {
+        Self {
+            creator,
+            repository,
+            queue,
+            config,
+        }
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval_secs));
+        loop {
+            interval.tick().await;
+            self.run_once().await;
+        }
+    }
+
+    async fn run_once(&self) {
+        let origins: Vec<MediaOrigin> = match self.repository.list().await {
+            Ok(fxs) => fxs.into_iter().map(|fx| fx.media).collect(),
+            Err(why) => {
+                log::error!("fail to list fx for cache maintenance, {:?}", why);
+                return;
+            }
+        };
+        match self.collect_garbage(&origins).await {
+            Ok(0) => (),
+            Ok(collected) => log::info!("collected {} unreferenced cache entries", collected),
+            Err(why) => log::error!("fail to collect cache entries, {:?}", why),
+        }
+        if let Err(why) = self.prewarm(origins).await {
+            log::error!("fail to prewarm the cache, {:?}", why);
+        }
+    }
+
+    async fn collect_garbage(&self, origins: &[MediaOrigin]) -> io::Result<usize> {
+        let store = self.creator.store();
+        let entries = store.list().await?;
+        let stored: HashSet<&str> = entries.iter().map(|entry| entry.key.as_str()).collect();
+        let mut referenced = HashSet::new();
+        for origin in origins {
+            referenced.extend(origin.upload_keys());
+            let key = self.creator.cache_key(origin);
+            // entries of older formats are kept until they are migrated to their current key
+            if !stored.contains(key.as_str()) {
+                referenced.extend(self.creator.predecessor_keys(origin));
+            }
+            referenced.insert(key);
+        }
+        let grace_period = Duration::from_secs(self.config.grace_period_secs);
+        let now = SystemTime::now();
+        let mut collected = 0;
+        for entry in entries.iter() {
+            if referenced.contains(&entry.key) {
+                continue;
+            }
+            let age = now.duration_since(entry.modified).unwrap_or_default();
+            if age < grace_period {
+                continue;
+            }
+            match store.delete(&entry.key).await {
+                Ok(()) => collected += 1,
+                Err(why) => log::error!("fail to delete cache entry {}, {:?}", entry.key, why),
+            }
+        }
+        Ok(collected)
+    }
+
+    async fn prewarm(&self, origins: Vec<MediaOrigin>) -> io::Result<()> {
+        let stored: HashSet<String> = self
+            .creator
+            .store()
+            .list()
+            .await?
+            .into_iter()
+            .map(|entry| entry.key)
+            .collect();
+        let missing = origins
+            .into_iter()
+            .filter(|origin| !stored.contains(&self.creator.cache_key(origin)));
+        futures::stream::iter(missing)
+            .for_each_concurrent(self.config.prewarm_concurrency, |origin| async move {
+                let owner = JobOwner {
+                    guild: None,
+                    user: None,
+                };
+                let progress = ProgressReporter::default();
+                let _job = match self.queue.join(owner, &progress).await {
+                    Ok(job) => job,
+                    Err(why) => {
+                        log::error!("fail to queue the prewarm of {}, {:?}", origin.source, why);
+                        return;
+                    }
+                };
+                let mut media = match self.creator.create(&origin).await {
+                    Ok(media) => media,
+                    Err(why) => {
+                        log::error!("fail to prewarm {}, {:?}", origin.source, why);
+                        return;
+                    }
+                };
+                // reading to the end waits for the render, the job runs until then
+                match tokio::io::copy(&mut media, &mut tokio::io::sink()).await {
+                    Ok(_) => log::info!("prewarmed {}", origin.source),
+                    Err(why) => log::error!("fail to prewarm {}, {:?}", origin.source, why),
+                }
+            })
+            .await;
+        Ok(())
+    }
+}
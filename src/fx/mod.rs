@@ -0,0 +1,2398 @@
+use chrono::serde::ts_seconds::{deserialize as from_ts, serialize as to_ts};
+use chrono::{DateTime, Utc};
+use mongodb::bson::doc;
+use serde::{Deserialize, Serialize};
+use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::id::{AttachmentId, GuildId, InteractionId, UserId};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Display};
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::fs::{self, File};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::Child;
+
+use async_trait::async_trait;
+use futures::TryStreamExt;
+
+use crate::audio::{self, DecodedMedia};
+use crate::config;
+use crate::error::UserFacing;
+use crate::ioutils::{TappableReader, Tapper};
+use crate::tools::{ExternalTool, Toolchain};
+use crate::waveform;
+use composite::{CompositeParseError, Join, PartSpec, Segment, SegmentSpec};
+use effect::Effect;
+use hot::HotCache;
+use process::{ProcessPipeline, Stage, StageFailure};
+use progress::{Progress, ProgressReporter};
+use queue::{JobOwner, JobPermit, QueueError, QueuedMedia, RenderQueue};
+
+pub mod composite;
+pub mod effect;
+pub mod hot;
+pub mod maintenance;
+pub mod process;
+pub mod progress;
+pub mod queue;
+
+#[derive(Debug)]
+pub enum StoreGetError {
+    NotFound,
+    IO(io::Error),
+}
+
+impl Display for StoreGetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreGetError::NotFound => write!(f, "not found"),
+            StoreGetError::IO(why) => write!(f, "{}", why),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StorePutError {
+    AlreadyExist,
+    IO(io::Error),
+}
+
+impl Display for StorePutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorePutError::AlreadyExist => write!(f, "already exists"),
+            StorePutError::IO(why) => write!(f, "{}", why),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StoreEntry {
+    pub key: String,
+    pub modified: SystemTime,
+}
+
+#[async_trait]
+pub trait Store: Sync + Send + 'static {
+    type Output: AsyncRead + Send + Unpin;
+    async fn get(&self, key: &str) -> Result<Self::Output, StoreGetError>;
+    /// Path of the entry on the local file system, for tools that must seek in it.
+    async fn path(&self, key: &str) -> Result<PathBuf, StoreGetError>;
+    async fn put<R: AsyncRead + Send + Unpin>(
+        &self,
+        key: &str,
+        mut data: R,
+    ) -> Result<(), StorePutError>;
+    async fn list(&self) -> io::Result<Vec<StoreEntry>>;
+    async fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+#[derive(Clone)]
+pub struct LocalStore {
+    dir: PathBuf,
+}
+
+#[async_trait]
+impl Store for LocalStore {
+    type Output = File;
+    async fn get(&self, key: &str) -> Result<Self::Output, StoreGetError> {
+        let file = self.dir.join(key);
+        File::open(file).await.map_err(|err| match err {
+            err if err.kind() == io::ErrorKind::NotFound => StoreGetError::NotFound,
+            err => StoreGetError::IO(err),
+        })
+    }
+
+    async fn path(&self, key: &str) -> Result<PathBuf, StoreGetError> {
+        let path = self.dir.join(key);
+        match Self::is_file_exists(&path).await {
+            Ok(true) => Ok(path),
+            Ok(false) => Err(StoreGetError::NotFound),
+            Err(why) => Err(StoreGetError::IO(why)),
+        }
+    }
+
+    async fn put<R: AsyncRead + Send + Unpin>(
+        &self,
+        key: &str,
+        mut data: R,
+    ) -> Result<(), StorePutError> {
+        let file = self.dir.join(key);
+        let exists = Self::is_file_exists(&file)
+            .await
+            .map_err(StorePutError::IO)?;
+        if exists {
+            return Err(StorePutError::AlreadyExist);
+        }
+        // write to a partial file first, so a concurrent get never sees a truncated entry
+        let partial = self.dir.join(format!("{}.partial", key));
+        let mut partial_file = File::create(&partial).await.map_err(StorePutError::IO)?;
+        if let Err(why) = tokio::io::copy(&mut data, &mut partial_file).await {
+            let _result = fs::remove_file(&partial).await;
+            return Err(StorePutError::IO(why));
+        }
+        fs::rename(partial, file).await.map_err(StorePutError::IO)
+    }
+
+    async fn list(&self) -> io::Result<Vec<StoreEntry>> {
+        let mut entries = vec![];
+        let mut dir = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if !metadata.is_file() {
+                continue;
+            }
+            if let Some(key) = entry.file_name().to_str() {
+                entries.push(StoreEntry {
+                    key: key.to_string(),
+                    modified: metadata.modified()?,
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        fs::remove_file(self.dir.join(key)).await
+    }
+}
+
+impl LocalStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+    async fn is_file_exists<P: AsRef<Path>>(path: P) -> io::Result<bool> {
+        match fs::metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Media file uploaded by a user, the original is kept in the [`Store`] under [`Upload::key`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Upload {
+    pub id: AttachmentId,
+    pub filename: String,
+    pub url: String,
+}
+
+impl Upload {
+    pub fn key(&self) -> String {
+        format!("upload-{}", self.id)
+    }
+}
+
+/// Text read out by the text-to-speech engine.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Speech {
+    pub text: String,
+    /// voice of the engine, its default voice is used without one
+    pub voice: Option<String>,
+}
+
+/// Where the media of an origin comes from. Untagged, so origins stored before uploads were
+/// supported still read as [`Source::Url`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum Source {
+    Url {
+        url: String,
+    },
+    Attachment {
+        attachment: Upload,
+    },
+    Speech {
+        speech: Speech,
+    },
+    /// parts played one after another, rendered into a single media
+    Composite {
+        segments: Vec<Segment>,
+    },
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Url { url } => write!(f, "{}", url),
+            Source::Attachment { attachment } => write!(f, "{}", attachment.filename),
+            Source::Speech { speech } => write!(f, "{}", speech.text),
+            Source::Composite { segments } => {
+                for (index, segment) in segments.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, " → ")?;
+                    }
+                    write!(f, "{}", segment)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Longest cut of an fx.
+pub const MAX_LENGTH: Duration = Duration::from_secs(20);
+const MIN_LENGTH: Duration = Duration::from_millis(100);
+
+/// A small adjustment of a cut, in milliseconds either way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Nudge {
+    Start(i64),
+    Length(i64),
+}
+
+fn shift(duration: Duration, millis: i64) -> Duration {
+    let delta = Duration::from_millis(millis.unsigned_abs());
+    if millis < 0 {
+        duration.saturating_sub(delta)
+    } else {
+        duration + delta
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MediaOrigin {
+    #[serde(flatten)]
+    pub source: Source,
+    pub start: Duration,
+    pub length: Duration,
+    /// processing applied to the cut, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub effects: Vec<Effect>,
+}
+
+/// Bumped whenever the layout of cache keys or the canonical encoding changes.
+const CACHE_KEY_SCHEME: &str = "v2";
+
+/// Pipeline that rendered every entry cached before keys were versioned.
+const LEGACY_SIGNATURE: CreatorSignature = CreatorSignature {
+    name: "youtube-dl",
+    version: 1,
+    format: "mp3",
+};
+
+/// Unambiguous encoding of values fed into a hash: every field is tagged and length-prefixed,
+/// and integers are little-endian regardless of the platform.
+#[derive(Default)]
+struct CanonicalEncoder {
+    hasher: Sha256,
+}
+
+impl CanonicalEncoder {
+    fn field(&mut self, tag: &str, value: &[u8]) -> &mut Self {
+        for part in [tag.as_bytes(), value] {
+            self.hasher.update((part.len() as u64).to_le_bytes());
+            self.hasher.update(part);
+        }
+        self
+    }
+
+    fn str(&mut self, tag: &str, value: &str) -> &mut Self {
+        self.field(tag, value.as_bytes())
+    }
+
+    fn u64(&mut self, tag: &str, value: u64) -> &mut Self {
+        self.field(tag, &value.to_le_bytes())
+    }
+
+    fn finish(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+impl MediaOrigin {
+    /// Key of the media of the origin rendered by the creator of `signature`. Use
+    /// [`Creator::cache_key`] instead, which also covers how the parts of composites are rendered.
+    fn cache_key(&self, signature: &CreatorSignature) -> String {
+        Self::signed_key(signature, |encoder| self.encode(encoder))
+    }
+
+    /// Key of a composite whose parts are cached under `part_keys`, in the order of its segments.
+    /// Parts are encoded by their keys, so a part rendered differently changes the composite too.
+    fn composite_cache_key(&self, signature: &CreatorSignature, part_keys: &[String]) -> String {
+        let segments = match &self.source {
+            Source::Composite { segments } => segments,
+            _ => return self.cache_key(signature),
+        };
+        Self::signed_key(signature, |encoder| {
+            encoder.u64("segments", segments.len() as u64);
+            for (segment, key) in segments.iter().zip(part_keys) {
+                encoder
+                    .str("join", &segment.join.to_string())
+                    .str("part", key);
+            }
+            self.encode_cut(encoder);
+        })
+    }
+
+    fn signed_key(
+        signature: &CreatorSignature,
+        encode: impl FnOnce(&mut CanonicalEncoder),
+    ) -> String {
+        let mut encoder = CanonicalEncoder::default();
+        encoder
+            .str("creator", signature.name)
+            .u64("version", signature.version as u64)
+            .str("format", signature.format);
+        encode(&mut encoder);
+        format!(
+            "{}-{}.{}",
+            CACHE_KEY_SCHEME,
+            encoder.finish(),
+            signature.format
+        )
+    }
+
+    // fields added later must be skipped when they hold their default value, so existing keys
+    // stay valid
+    fn encode(&self, encoder: &mut CanonicalEncoder) {
+        match &self.source {
+            Source::Url { url } => {
+                encoder.str("url", url);
+            }
+            Source::Attachment { attachment } => {
+                encoder.str("attachment", &attachment.key());
+            }
+            Source::Speech { speech } => {
+                encoder.str("speech", &speech.text);
+                if let Some(voice) = &speech.voice {
+                    encoder.str("voice", voice);
+                }
+            }
+            // how composites were keyed before their parts were keyed by their creators
+            Source::Composite { segments } => {
+                encoder.u64("segments", segments.len() as u64);
+                for segment in segments.iter() {
+                    encoder.str("join", &segment.join.to_string());
+                    segment.origin.encode(encoder);
+                }
+            }
+        }
+        self.encode_cut(encoder);
+    }
+
+    fn encode_cut(&self, encoder: &mut CanonicalEncoder) {
+        encoder
+            .u64("start", self.start.as_millis() as u64)
+            .u64("length", self.length.as_millis() as u64);
+        for effect in self.effects.iter() {
+            encoder.str("effect", &effect.to_string());
+        }
+    }
+
+    /// Store keys of the original media the origin is cut from, for those kept in the store.
+    pub fn upload_keys(&self) -> Vec<String> {
+        match &self.source {
+            Source::Attachment { attachment } => vec![attachment.key()],
+            Source::Composite { segments } => segments
+                .iter()
+                .flat_map(|segment| segment.origin.upload_keys())
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Whether the source is longer media the cut can move around in.
+    pub fn can_cut(&self) -> bool {
+        matches!(self.source, Source::Url { .. } | Source::Attachment { .. })
+    }
+
+    /// The cut adjusted by `nudge`, kept within the media and the length limits.
+    pub fn nudged(&self, nudge: Nudge) -> MediaOrigin {
+        let (start, length) = match nudge {
+            Nudge::Start(millis) => (shift(self.start, millis), self.length),
+            Nudge::Length(millis) => (
+                self.start,
+                shift(self.length, millis).clamp(MIN_LENGTH, MAX_LENGTH),
+            ),
+        };
+        MediaOrigin {
+            start,
+            length,
+            ..self.clone()
+        }
+    }
+
+    /// The same cut widened by `margin` on both sides without effects, along with where the cut
+    /// lies in it. Only sources that can be cut anywhere have a context.
+    pub fn context(&self, margin: Duration) -> Option<(MediaOrigin, Range<Duration>)> {
+        if !matches!(self.source, Source::Url { .. } | Source::Attachment { .. }) {
+            return None;
+        }
+        let start = self.start.saturating_sub(margin);
+        let offset = self.start - start;
+        let origin = MediaOrigin {
+            source: self.source.clone(),
+            start,
+            length: offset + self.length + margin,
+            effects: vec![],
+        };
+        Some((origin, offset..offset + self.length))
+    }
+
+    /// Key of entries cached before keys were versioned, only meaningful for media rendered by
+    /// the legacy pipeline. Those were plain cuts at whole seconds, other origins would be keyed
+    /// the same as the cut they're rounded to.
+    fn legacy_cache_key(&self, signature: &CreatorSignature) -> Option<String> {
+        let url = match &self.source {
+            Source::Url { url } if *signature == LEGACY_SIGNATURE => url,
+            _ => return None,
+        };
+        let whole_seconds = self.start.subsec_nanos() == 0 && self.length.subsec_nanos() == 0;
+        if !self.effects.is_empty() || !whole_seconds {
+            return None;
+        }
+        let mut input = vec![];
+        input.extend_from_slice(url.as_bytes());
+        input.extend_from_slice(&self.start.as_secs().to_ne_bytes());
+        input.extend_from_slice(&self.length.as_secs().to_ne_bytes());
+        Some(format!("{:?}", md5::compute(input)))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DiscordOrigin {
+    pub guild: Option<GuildId>,
+    pub interaction: InteractionId,
+    pub author: Option<UserId>,
+    #[serde(serialize_with = "to_ts", deserialize_with = "from_ts")]
+    pub drafted_at: DateTime<Utc>,
+}
+
+impl From<ApplicationCommandInteraction> for DiscordOrigin {
+    fn from(interaction: ApplicationCommandInteraction) -> Self {
+        Self {
+            guild: interaction.guild_id,
+            interaction: interaction.id,
+            author: interaction.member.map(|member| member.user.id),
+            drafted_at: Utc::now(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Fx {
+    pub name: String,
+    pub description: String,
+    pub discord: DiscordOrigin,
+    pub media: MediaOrigin,
+    /// what's known about the source when the fx was drafted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<SourceMetadata>,
+}
+
+/// Details of the media an fx is cut from, as reported by the downloader.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SourceMetadata {
+    pub title: Option<String>,
+    pub uploader: Option<String>,
+    pub duration: Option<Duration>,
+    pub thumbnail: Option<String>,
+}
+
+impl SourceMetadata {
+    /// Whether the cut ends within the source, always true if its duration is unknown.
+    pub fn contains(&self, origin: &MediaOrigin) -> bool {
+        self.duration
+            .map_or(true, |duration| origin.start + origin.length <= duration)
+    }
+}
+
+/// The part of the JSON dump of the downloader kept as [`SourceMetadata`].
+#[derive(Deserialize)]
+struct DownloaderInfo {
+    title: Option<String>,
+    uploader: Option<String>,
+    /// in seconds, fractional for some sites
+    duration: Option<f64>,
+    thumbnail: Option<String>,
+}
+
+impl From<DownloaderInfo> for SourceMetadata {
+    fn from(info: DownloaderInfo) -> Self {
+        SourceMetadata {
+            title: info.title,
+            uploader: info.uploader,
+            duration: info
+                .duration
+                .filter(|secs| secs.is_finite() && *secs >= 0.0)
+                .map(Duration::from_secs_f64),
+            thumbnail: info.thumbnail,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RepositoryAddError {
+    IO(mongodb::error::Error),
+    AlreadyExists,
+}
+
+#[derive(Debug)]
+pub enum RepositoryGetError {
+    IO(mongodb::error::Error),
+    NotFound,
+}
+
+#[async_trait]
+pub trait Repository: Send + Sync {
+    async fn add_draft(&self, fx: Fx) -> Result<(), RepositoryAddError>;
+    async fn add(&self, fx: Fx) -> Result<(), RepositoryAddError>;
+    async fn get(&self, identity: &FxIdentity) -> Result<Fx, RepositoryGetError>;
+    async fn list(&self) -> Result<Vec<Fx>, mongodb::error::Error>;
+}
+
+pub struct MongoDBRepository {
+    client: mongodb::Database,
+}
+
+#[async_trait]
+impl Repository for MongoDBRepository {
+    async fn add_draft(&self, fx: Fx) -> Result<(), RepositoryAddError> {
+        self.client
+            .collection("fx_drafts")
+            .insert_one(fx, None)
+            .await
+            .map(|_| ())
+            .map_err(RepositoryAddError::IO)
+    }
+    async fn add(&self, fx: Fx) -> Result<(), RepositoryAddError> {
+        self.client
+            .collection("fx")
+            .insert_one(fx, None)
+            .await
+            .map(|_| ())
+            .map_err(RepositoryAddError::IO)
+    }
+
+    async fn get(&self, identity: &FxIdentity) -> Result<Fx, RepositoryGetError> {
+        let FxIdentity(_guild_id, name) = identity;
+        let condition = doc! {
+            "name": name,
+        };
+        match self
+            .client
+            .collection("fx")
+            .find_one(condition, None)
+            .await
+            .map_err(RepositoryGetError::IO)
+        {
+            Ok(Some(result)) => Ok(result),
+            Ok(None) => Err(RepositoryGetError::NotFound),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<Fx>, mongodb::error::Error> {
+        self.client
+            .collection("fx")
+            .find(None, None)
+            .await?
+            .try_collect()
+            .await
+    }
+}
+
+impl MongoDBRepository {
+    pub fn new(client: mongodb::Database) -> Self {
+        Self { client }
+    }
+}
+
+/// Encoding of rendered media.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Mp3,
+    /// Opus in Ogg at 48kHz with 20ms frames, which songbird sends without decoding
+    Opus,
+}
+
+impl Format {
+    /// Name of the format in cache keys, it's also the extension of stored entries.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Format::Mp3 => "mp3",
+            Format::Opus => "ogg",
+        }
+    }
+
+    fn ffmpeg_args(&self) -> &'static [&'static str] {
+        match self {
+            Format::Mp3 => &["-f", "mp3"],
+            Format::Opus => &[
+                "-c:a",
+                "libopus",
+                "-b:a",
+                "96k",
+                "-ar",
+                "48000",
+                "-ac",
+                "2",
+                "-frame_duration",
+                "20",
+                "-f",
+                "ogg",
+            ],
+        }
+    }
+}
+
+/// Format media is rendered and stored in. Previews are exported to mp3 instead, so they play
+/// everywhere.
+pub const STORAGE_FORMAT: Format = Format::Opus;
+
+/// Identifies the processing a [`Creator`] applies to an origin. A creator that changes how it
+/// renders media must bump its version, otherwise stale renders keep being served from the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CreatorSignature {
+    pub name: &'static str,
+    pub version: u32,
+    pub format: &'static str,
+}
+
+#[derive(Debug)]
+pub enum UploadError {
+    Unsupported,
+    Store(StorePutError),
+}
+
+#[async_trait]
+pub trait Creator: Send + Sync + 'static {
+    type Output: AsyncRead + Send + Unpin + 'static;
+    type Error: Debug + Display + Send + Sync + UserFacing;
+    fn signature(&self, origin: &MediaOrigin) -> CreatorSignature;
+    async fn create(&self, origin: &MediaOrigin) -> Result<Self::Output, Self::Error>;
+    /// Like [`Creator::create`], telling `progress` how creating the media goes.
+    async fn create_reporting(
+        &self,
+        origin: &MediaOrigin,
+        _progress: &ProgressReporter,
+    ) -> Result<Self::Output, Self::Error> {
+        self.create(origin).await
+    }
+    /// Key the media of `origin` is cached under, it changes whenever the media would be rendered
+    /// differently.
+    fn cache_key(&self, origin: &MediaOrigin) -> String {
+        origin.cache_key(&self.signature(origin))
+    }
+    /// Whether the media of `origin` can be created without rendering it, e.g. it's cached or
+    /// being rendered already.
+    async fn is_rendered(&self, _origin: &MediaOrigin) -> bool {
+        false
+    }
+    /// Keep `data` as the original media of `upload`, so origins cut from it can be created.
+    async fn upload(&self, _upload: &Upload, _data: &[u8]) -> Result<(), UploadError> {
+        Err(UploadError::Unsupported)
+    }
+    /// Look up the details of `source` without downloading it.
+    async fn probe(&self, _source: &Source) -> Result<SourceMetadata, ProbeError> {
+        Err(ProbeError::Unsupported)
+    }
+}
+
+#[derive(Debug)]
+pub enum ProbeError {
+    Unsupported,
+    Stage(StageFailure),
+    Parse(serde_json::Error),
+}
+
+impl Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeError::Unsupported => write!(f, "the source has no details"),
+            ProbeError::Stage(failure) => write!(f, "{}", failure),
+            ProbeError::Parse(why) => write!(f, "unexpected details: {}", why),
+        }
+    }
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{}.{:03}", duration.as_secs(), duration.subsec_millis())
+}
+
+/// Where ffmpeg reads the media to cut from.
+enum CutInput {
+    /// streamed in, only for formats readable from the start to the end
+    Pipe(Stdio),
+    /// a file ffmpeg can seek in, needed e.g. for MP4 with its index at the end
+    File(PathBuf),
+}
+
+/// Run ffmpeg cutting `origin` out of the media read from `input` and applying its effects, the
+/// output is in the storage format on stdout.
+fn cut(ffmpeg: &ExternalTool, origin: &MediaOrigin, input: CutInput) -> io::Result<Child> {
+    let mut command = ffmpeg.command();
+    command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .arg("-ss")
+        .arg(seconds(origin.start))
+        .arg("-t")
+        .arg(seconds(origin.length))
+        .args(&["-hide_banner", "-loglevel", "error"]);
+    match input {
+        CutInput::Pipe(input) => command.stdin(input).args(&["-i", "-"]),
+        CutInput::File(path) => command.stdin(Stdio::null()).arg("-i").arg(path),
+    };
+    if let Some(filter) = effect::filter_graph(&origin.effects) {
+        command.arg("-af").arg(filter);
+    }
+    command
+        .args(STORAGE_FORMAT.ffmpeg_args())
+        .arg("-")
+        .kill_on_drop(true)
+        .spawn()
+}
+
+/// Convert media read from `media` to `format` through ffmpeg.
+pub async fn transcode<M>(
+    ffmpeg: &ExternalTool,
+    mut media: M,
+    format: Format,
+) -> Result<ProcessPipeline, StageFailure>
+where
+    M: AsyncRead + Send + Unpin + 'static,
+{
+    let mut child = ffmpeg
+        .command()
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .args(&["-hide_banner", "-loglevel", "error"])
+        .args(&["-i", "-"])
+        .args(format.ffmpeg_args())
+        .arg("-")
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|error| StageFailure::IO {
+            stage: "ffmpeg",
+            error,
+        })?;
+    let mut input = child.stdin.take().unwrap();
+    tokio::spawn(async move {
+        if let Err(why) = tokio::io::copy(&mut media, &mut input).await {
+            log::debug!("stop feeding media to ffmpeg, {:?}", why);
+        }
+    });
+    let mut pipeline = ProcessPipeline::new(vec![Stage {
+        name: "ffmpeg",
+        child,
+        timeout: ffmpeg.timeout,
+        stderr_lines: None,
+    }]);
+    pipeline.ready().await?;
+    Ok(pipeline)
+}
+
+#[derive(Debug)]
+pub enum YoutubeDLCreateError {
+    UnsupportedSource,
+    YoutubeDL(io::Error),
+    FFmepg(io::Error),
+    /// a stage exited unsuccessfully or timed out, with its exit status and stderr tail
+    Stage(StageFailure),
+}
+
+impl Display for YoutubeDLCreateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            YoutubeDLCreateError::UnsupportedSource => write!(f, "the source is not a link"),
+            YoutubeDLCreateError::YoutubeDL(why) => write!(f, "cannot run the downloader: {}", why),
+            YoutubeDLCreateError::FFmepg(why) => write!(f, "cannot run ffmpeg: {}", why),
+            YoutubeDLCreateError::Stage(failure) => write!(f, "{}", failure),
+        }
+    }
+}
+
+pub struct YoutubeDLCreator {
+    tools: Toolchain,
+}
+
+#[async_trait]
+impl Creator for YoutubeDLCreator {
+    type Output = ProcessPipeline;
+    type Error = YoutubeDLCreateError;
+
+    fn signature(&self, _origin: &MediaOrigin) -> CreatorSignature {
+        Self::SIGNATURE
+    }
+
+    async fn create(&self, origin: &MediaOrigin) -> Result<Self::Output, Self::Error> {
+        self.create_reporting(origin, &ProgressReporter::default())
+            .await
+    }
+
+    async fn create_reporting(
+        &self,
+        origin: &MediaOrigin,
+        progress: &ProgressReporter,
+    ) -> Result<Self::Output, Self::Error> {
+        let url = match &origin.source {
+            Source::Url { url } => url,
+            _ => return Err(YoutubeDLCreateError::UnsupportedSource),
+        };
+        progress.report(Progress::Downloading(None));
+        let mut ytdl = self
+            .tools
+            .downloader
+            .command()
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .args([url.as_str(), "-o", "-", "--audio-format", "best"])
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(YoutubeDLCreateError::YoutubeDL)?;
+        let ytdl_out: Stdio = ytdl
+            .stdout
+            .take()
+            .unwrap()
+            .try_into()
+            .map_err(YoutubeDLCreateError::YoutubeDL)?;
+        let ffmpeg = cut(&self.tools.ffmpeg, origin, CutInput::Pipe(ytdl_out))
+            .map_err(YoutubeDLCreateError::FFmepg)?;
+        let (stderr_lines, lines) = tokio::sync::mpsc::unbounded_channel();
+        progress.watch_downloader(lines);
+        let mut pipeline = ProcessPipeline::new(vec![
+            Stage {
+                name: "downloader",
+                child: ytdl,
+                timeout: self.tools.downloader.timeout,
+                stderr_lines: Some(stderr_lines),
+            },
+            Stage {
+                name: "ffmpeg",
+                child: ffmpeg,
+                timeout: self.tools.ffmpeg.timeout,
+                stderr_lines: None,
+            },
+        ]);
+        pipeline
+            .ready()
+            .await
+            .map_err(YoutubeDLCreateError::Stage)?;
+        Ok(pipeline)
+    }
+
+    async fn probe(&self, source: &Source) -> Result<SourceMetadata, ProbeError> {
+        const STAGE: &str = "downloader";
+        let url = match source {
+            Source::Url { url } => url,
+            _ => return Err(ProbeError::Unsupported),
+        };
+        let child = self
+            .tools
+            .downloader
+            .command()
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .args(["--dump-single-json", "--no-playlist", url.as_str()])
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|error| {
+                ProbeError::Stage(StageFailure::IO {
+                    stage: STAGE,
+                    error,
+                })
+            })?;
+        let timeout = self.tools.downloader.timeout;
+        let output = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+            Ok(output) => output.map_err(|error| {
+                ProbeError::Stage(StageFailure::IO {
+                    stage: STAGE,
+                    error,
+                })
+            })?,
+            Err(_) => {
+                return Err(ProbeError::Stage(StageFailure::TimedOut {
+                    stage: STAGE,
+                    timeout,
+                    stderr: String::new(),
+                }))
+            }
+        };
+        if !output.status.success() {
+            return Err(ProbeError::Stage(StageFailure::Exited {
+                stage: STAGE,
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            }));
+        }
+        serde_json::from_slice::<DownloaderInfo>(&output.stdout)
+            .map(SourceMetadata::from)
+            .map_err(ProbeError::Parse)
+    }
+}
+
+impl YoutubeDLCreator {
+    const SIGNATURE: CreatorSignature = CreatorSignature {
+        name: "youtube-dl",
+        version: 1,
+        format: STORAGE_FORMAT.name(),
+    };
+
+    pub fn new(tools: Toolchain) -> Self {
+        Self { tools }
+    }
+}
+
+#[derive(Debug)]
+pub enum AttachmentCreateError {
+    UnsupportedSource,
+    Upload(StoreGetError),
+    FFmpeg(io::Error),
+    Stage(StageFailure),
+}
+
+impl Display for AttachmentCreateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttachmentCreateError::UnsupportedSource => write!(f, "the source is not an upload"),
+            AttachmentCreateError::Upload(StoreGetError::NotFound) => {
+                write!(f, "the uploaded file is gone")
+            }
+            AttachmentCreateError::Upload(why) => write!(f, "cannot read the upload: {}", why),
+            AttachmentCreateError::FFmpeg(why) => write!(f, "cannot run ffmpeg: {}", why),
+            AttachmentCreateError::Stage(failure) => write!(f, "{}", failure),
+        }
+    }
+}
+
+/// Creator cutting media out of files uploaded by users, the originals are kept in `store`.
+pub struct AttachmentCreator<S: Store> {
+    store: S,
+    tools: Toolchain,
+}
+
+#[async_trait]
+impl<S> Creator for AttachmentCreator<S>
+where
+    S: Store,
+{
+    type Output = ProcessPipeline;
+    type Error = AttachmentCreateError;
+
+    fn signature(&self, _origin: &MediaOrigin) -> CreatorSignature {
+        Self::SIGNATURE
+    }
+
+    async fn create(&self, origin: &MediaOrigin) -> Result<Self::Output, Self::Error> {
+        let upload = match &origin.source {
+            Source::Attachment { attachment } => attachment,
+            _ => return Err(AttachmentCreateError::UnsupportedSource),
+        };
+        // read in place rather than piped, videos from phones keep their index at the end
+        let original = self
+            .store
+            .path(&upload.key())
+            .await
+            .map_err(AttachmentCreateError::Upload)?;
+        let ffmpeg = cut(&self.tools.ffmpeg, origin, CutInput::File(original))
+            .map_err(AttachmentCreateError::FFmpeg)?;
+        let mut pipeline = ProcessPipeline::new(vec![Stage {
+            name: "ffmpeg",
+            child: ffmpeg,
+            timeout: self.tools.ffmpeg.timeout,
+            stderr_lines: None,
+        }]);
+        pipeline
+            .ready()
+            .await
+            .map_err(AttachmentCreateError::Stage)?;
+        Ok(pipeline)
+    }
+
+    async fn upload(&self, upload: &Upload, data: &[u8]) -> Result<(), UploadError> {
+        match self.store.put(&upload.key(), data).await {
+            // an attachment never changes, so the stored one is the same file
+            Ok(()) | Err(StorePutError::AlreadyExist) => Ok(()),
+            Err(why) => Err(UploadError::Store(why)),
+        }
+    }
+}
+
+impl<S: Store> AttachmentCreator<S> {
+    const SIGNATURE: CreatorSignature = CreatorSignature {
+        name: "attachment",
+        version: 1,
+        format: STORAGE_FORMAT.name(),
+    };
+
+    pub fn new(store: S, tools: Toolchain) -> Self {
+        Self { store, tools }
+    }
+}
+
+#[derive(Debug)]
+pub enum SpeechCreateError {
+    UnsupportedSource,
+    /// no text-to-speech engine is present on the host
+    Unavailable,
+    Speech(io::Error),
+    FFmpeg(io::Error),
+    Stage(StageFailure),
+}
+
+impl Display for SpeechCreateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpeechCreateError::UnsupportedSource => write!(f, "the source is not a text"),
+            SpeechCreateError::Unavailable => write!(f, "text-to-speech is not available"),
+            SpeechCreateError::Speech(why) => {
+                write!(f, "cannot run the text-to-speech engine: {}", why)
+            }
+            SpeechCreateError::FFmpeg(why) => write!(f, "cannot run ffmpeg: {}", why),
+            SpeechCreateError::Stage(failure) => write!(f, "{}", failure),
+        }
+    }
+}
+
+/// Creator synthesizing speech with an espeak-ng compatible engine.
+pub struct SpeechCreator {
+    tools: Toolchain,
+}
+
+#[async_trait]
+impl Creator for SpeechCreator {
+    type Output = ProcessPipeline;
+    type Error = SpeechCreateError;
+
+    fn signature(&self, _origin: &MediaOrigin) -> CreatorSignature {
+        Self::SIGNATURE
+    }
+
+    async fn create(&self, origin: &MediaOrigin) -> Result<Self::Output, Self::Error> {
+        let speech = match &origin.source {
+            Source::Speech { speech } => speech,
+            _ => return Err(SpeechCreateError::UnsupportedSource),
+        };
+        let engine = self
+            .tools
+            .speech
+            .as_ref()
+            .ok_or(SpeechCreateError::Unavailable)?;
+        let mut command = engine.command();
+        if let Some(voice) = &speech.voice {
+            command.arg("-v").arg(voice);
+        }
+        // the text is read from stdin, so it's never taken as an option
+        let mut engine_child = command
+            .args(["--stdin", "--stdout"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(SpeechCreateError::Speech)?;
+        let mut input = engine_child.stdin.take().unwrap();
+        let text = speech.text.clone();
+        tokio::spawn(async move {
+            if let Err(why) = input.write_all(text.as_bytes()).await {
+                log::debug!("stop feeding the text to the engine, {:?}", why);
+            }
+        });
+        let wave: Stdio = engine_child
+            .stdout
+            .take()
+            .unwrap()
+            .try_into()
+            .map_err(SpeechCreateError::Speech)?;
+        let ffmpeg = cut(&self.tools.ffmpeg, origin, CutInput::Pipe(wave))
+            .map_err(SpeechCreateError::FFmpeg)?;
+        let mut pipeline = ProcessPipeline::new(vec![
+            Stage {
+                name: "text-to-speech",
+                child: engine_child,
+                timeout: engine.timeout,
+                stderr_lines: None,
+            },
+            Stage {
+                name: "ffmpeg",
+                child: ffmpeg,
+                timeout: self.tools.ffmpeg.timeout,
+                stderr_lines: None,
+            },
+        ]);
+        pipeline.ready().await.map_err(SpeechCreateError::Stage)?;
+        Ok(pipeline)
+    }
+}
+
+impl SpeechCreator {
+    const SIGNATURE: CreatorSignature = CreatorSignature {
+        name: "speech",
+        version: 1,
+        format: STORAGE_FORMAT.name(),
+    };
+
+    pub fn new(tools: Toolchain) -> Self {
+        Self { tools }
+    }
+}
+
+#[derive(Debug)]
+pub enum MediaCreateError {
+    YoutubeDL(YoutubeDLCreateError),
+    Attachment(AttachmentCreateError),
+    Speech(SpeechCreateError),
+    /// a part of a composite failed
+    Part(Box<MediaCreateError>),
+    /// the rendered media of a part cannot be read
+    Decode(io::Error),
+    FFmpeg(io::Error),
+    Stage(StageFailure),
+}
+
+impl Display for MediaCreateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MediaCreateError::YoutubeDL(why) => write!(f, "{}", why),
+            MediaCreateError::Attachment(why) => write!(f, "{}", why),
+            MediaCreateError::Speech(why) => write!(f, "{}", why),
+            MediaCreateError::Part(why) => write!(f, "{}", why),
+            MediaCreateError::Decode(why) => write!(f, "{}", why),
+            MediaCreateError::FFmpeg(why) => write!(f, "cannot run ffmpeg: {}", why),
+            MediaCreateError::Stage(failure) => write!(f, "{}", failure),
+        }
+    }
+}
+
+/// Creator of every kind of [`Source`], handing each origin to the creator of its kind.
+pub struct MediaCreator<S: Store> {
+    youtube_dl: YoutubeDLCreator,
+    attachment: AttachmentCreator<S>,
+    speech: SpeechCreator,
+    ffmpeg: ExternalTool,
+    /// cache of the [`CachedCreator`] around it, parts of composites are read from there
+    cache: S,
+}
+
+#[async_trait]
+impl<S> Creator for MediaCreator<S>
+where
+    S: Store,
+    S::Output: 'static,
+{
+    type Output = ProcessPipeline;
+    type Error = MediaCreateError;
+
+    fn signature(&self, origin: &MediaOrigin) -> CreatorSignature {
+        match origin.source {
+            Source::Url { .. } => self.youtube_dl.signature(origin),
+            Source::Attachment { .. } => self.attachment.signature(origin),
+            Source::Speech { .. } => self.speech.signature(origin),
+            Source::Composite { .. } => Self::COMPOSITE_SIGNATURE,
+        }
+    }
+
+    fn cache_key(&self, origin: &MediaOrigin) -> String {
+        match &origin.source {
+            Source::Composite { segments } => {
+                let part_keys: Vec<String> = segments
+                    .iter()
+                    .map(|segment| self.cache_key(&segment.origin))
+                    .collect();
+                origin.composite_cache_key(&Self::COMPOSITE_SIGNATURE, &part_keys)
+            }
+            _ => origin.cache_key(&self.signature(origin)),
+        }
+    }
+
+    async fn create(&self, origin: &MediaOrigin) -> Result<Self::Output, Self::Error> {
+        match origin.source {
+            Source::Url { .. } => self
+                .youtube_dl
+                .create(origin)
+                .await
+                .map_err(MediaCreateError::YoutubeDL),
+            Source::Attachment { .. } => self
+                .attachment
+                .create(origin)
+                .await
+                .map_err(MediaCreateError::Attachment),
+            Source::Speech { .. } => self
+                .speech
+                .create(origin)
+                .await
+                .map_err(MediaCreateError::Speech),
+            Source::Composite { ref segments } => self.concat(origin, segments).await,
+        }
+    }
+
+    async fn create_reporting(
+        &self,
+        origin: &MediaOrigin,
+        progress: &ProgressReporter,
+    ) -> Result<Self::Output, Self::Error> {
+        match origin.source {
+            Source::Url { .. } => self
+                .youtube_dl
+                .create_reporting(origin, progress)
+                .await
+                .map_err(MediaCreateError::YoutubeDL),
+            _ => self.create(origin).await,
+        }
+    }
+
+    async fn upload(&self, upload: &Upload, data: &[u8]) -> Result<(), UploadError> {
+        self.attachment.upload(upload, data).await
+    }
+
+    async fn probe(&self, source: &Source) -> Result<SourceMetadata, ProbeError> {
+        match source {
+            Source::Url { .. } => self.youtube_dl.probe(source).await,
+            _ => Err(ProbeError::Unsupported),
+        }
+    }
+}
+
+impl<S> MediaCreator<S>
+where
+    S: Store,
+    S::Output: 'static,
+{
+    const COMPOSITE_SIGNATURE: CreatorSignature = CreatorSignature {
+        name: "composite",
+        version: 1,
+        format: STORAGE_FORMAT.name(),
+    };
+
+    /// Originals of uploads are kept in `store`, which is the cache media is served from too.
+    pub fn new(store: S, tools: Toolchain) -> Self
+    where
+        S: Clone,
+    {
+        Self {
+            youtube_dl: YoutubeDLCreator::new(tools.clone()),
+            attachment: AttachmentCreator::new(store.clone(), tools.clone()),
+            speech: SpeechCreator::new(tools.clone()),
+            ffmpeg: tools.ffmpeg,
+            cache: store,
+        }
+    }
+
+    /// Render the parts one by one, join their samples and encode the result with the effects
+    /// of the composite. Parts cached already, like the fx a combo is made of, aren't rendered
+    /// again.
+    async fn concat(
+        &self,
+        origin: &MediaOrigin,
+        segments: &[Segment],
+    ) -> Result<ProcessPipeline, MediaCreateError> {
+        let mut joined = vec![];
+        for segment in segments.iter() {
+            let key = self.cache_key(&segment.origin);
+            let samples = match self.cache.get(&key).await {
+                Ok(media) => self.decode(media).await?,
+                Err(why) => {
+                    if let StoreGetError::IO(why) = why {
+                        log::warn!("fail to read cache entry {}, {:?}", key, why);
+                    }
+                    let media = self
+                        .create(&segment.origin)
+                        .await
+                        .map_err(|why| MediaCreateError::Part(Box::new(why)))?;
+                    self.decode(media).await?
+                }
+            };
+            composite::join(&mut joined, &samples, &segment.join);
+        }
+        let pcm: Vec<u8> = joined
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        let mut command = self.ffmpeg.command();
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .args(&["-hide_banner", "-loglevel", "error"])
+            .args(&["-f", "s16le"])
+            .arg("-ar")
+            .arg(composite::SAMPLE_RATE.to_string())
+            .arg("-ac")
+            .arg(composite::CHANNELS.to_string())
+            .args(&["-i", "-"]);
+        if let Some(filter) = effect::filter_graph(&origin.effects) {
+            command.arg("-af").arg(filter);
+        }
+        let mut ffmpeg = command
+            .args(STORAGE_FORMAT.ffmpeg_args())
+            .arg("-")
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(MediaCreateError::FFmpeg)?;
+        let mut input = ffmpeg.stdin.take().unwrap();
+        tokio::spawn(async move {
+            if let Err(why) = input.write_all(&pcm).await {
+                log::debug!("stop feeding the composite to ffmpeg, {:?}", why);
+            }
+        });
+        let mut pipeline = ProcessPipeline::new(vec![Stage {
+            name: "ffmpeg",
+            child: ffmpeg,
+            timeout: self.ffmpeg.timeout,
+            stderr_lines: None,
+        }]);
+        pipeline.ready().await.map_err(MediaCreateError::Stage)?;
+        Ok(pipeline)
+    }
+
+    /// Decode the media of a part into interleaved samples at the rate of composites.
+    async fn decode<M>(&self, mut media: M) -> Result<Vec<i16>, MediaCreateError>
+    where
+        M: AsyncRead + Send + Unpin + 'static,
+    {
+        let mut ffmpeg = self
+            .ffmpeg
+            .command()
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .args(&["-hide_banner", "-loglevel", "error"])
+            .args(&["-i", "-"])
+            .args(&["-f", "s16le"])
+            .arg("-ar")
+            .arg(composite::SAMPLE_RATE.to_string())
+            .arg("-ac")
+            .arg(composite::CHANNELS.to_string())
+            .arg("-")
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(MediaCreateError::FFmpeg)?;
+        let mut input = ffmpeg.stdin.take().unwrap();
+        tokio::spawn(async move {
+            if let Err(why) = tokio::io::copy(&mut media, &mut input).await {
+                log::debug!("stop feeding a part to ffmpeg, {:?}", why);
+            }
+        });
+        let mut pipeline = ProcessPipeline::new(vec![Stage {
+            name: "ffmpeg",
+            child: ffmpeg,
+            timeout: self.ffmpeg.timeout,
+            stderr_lines: None,
+        }]);
+        let mut pcm = vec![];
+        pipeline
+            .read_to_end(&mut pcm)
+            .await
+            .map_err(MediaCreateError::Decode)?;
+        Ok(pcm
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect())
+    }
+}
+
+#[derive(Debug)]
+pub enum CachedCreatorError<StoreError, CreateError>
+where
+    StoreError: Debug,
+    CreateError: Debug,
+{
+    Cache(StoreError),
+    Create(CreateError),
+}
+
+impl<StoreError, CreateError> Display for CachedCreatorError<StoreError, CreateError>
+where
+    StoreError: Debug + Display,
+    CreateError: Debug + Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CachedCreatorError::Cache(why) => write!(f, "cache failed: {}", why),
+            CachedCreatorError::Create(why) => write!(f, "{}", why),
+        }
+    }
+}
+
+enum Flight {
+    Pending,
+    Running(Tapper),
+    Failed,
+}
+
+type InFlight = Arc<tokio::sync::Mutex<Flight>>;
+
+/// Creator that serves media from `store` when possible. Concurrent creations of the same
+/// origin share a single pipeline of the underlying creator.
+pub struct CachedCreator<C: Creator, S: Store> {
+    creator: Arc<C>,
+    store: Arc<S>,
+    ffmpeg: ExternalTool,
+    in_flight: Arc<Mutex<HashMap<String, InFlight>>>,
+}
+
+#[async_trait]
+impl<C, S> Creator for CachedCreator<C, S>
+where
+    C: Creator + 'static,
+    S: Store + 'static,
+    S::Output: Sync + Send + Unpin + 'static,
+    C::Output: Sync + Send + Unpin + 'static,
+    C::Error: Sync + Send,
+{
+    type Output = Box<dyn AsyncRead + Send + Unpin>;
+
+    type Error = CachedCreatorError<StoreGetError, C::Error>;
+
+    fn signature(&self, origin: &MediaOrigin) -> CreatorSignature {
+        self.creator.signature(origin)
+    }
+
+    fn cache_key(&self, origin: &MediaOrigin) -> String {
+        self.creator.cache_key(origin)
+    }
+
+    async fn create(&self, origin: &MediaOrigin) -> Result<Self::Output, Self::Error> {
+        self.create_reporting(origin, &ProgressReporter::default())
+            .await
+    }
+
+    async fn create_reporting(
+        &self,
+        origin: &MediaOrigin,
+        progress: &ProgressReporter,
+    ) -> Result<Self::Output, Self::Error> {
+        let key = self.creator.cache_key(origin);
+        match self.store.get(&key).await {
+            Ok(media) => return Ok(Box::new(media)),
+            Err(StoreGetError::NotFound) => (),
+            Err(why) => return Err(CachedCreatorError::Cache(why)),
+        }
+        loop {
+            let flight = self.join_flight(&key);
+            let mut state = flight.lock().await;
+            match &*state {
+                Flight::Running(tapper) => return Ok(Box::new(tapper.tap())),
+                // the leader of this flight failed, join or lead a new one
+                Flight::Failed => continue,
+                Flight::Pending => (),
+            }
+            let output: Self::Output = match self.migrate(origin).await {
+                Some(output) => Box::new(output),
+                None => match self.creator.create_reporting(origin, progress).await {
+                    Ok(output) => Box::new(output),
+                    Err(why) => {
+                        *state = Flight::Failed;
+                        Self::land(&self.in_flight, &key, &flight);
+                        return Err(CachedCreatorError::Create(why));
+                    }
+                },
+            };
+            let reader = TappableReader::new(output);
+            let tapped = reader.tap();
+            let to_store = reader.tap();
+            *state = Flight::Running(reader.tapper());
+            drop(state);
+            tokio::spawn(Self::drain(reader));
+            let store = self.store.clone();
+            let in_flight = self.in_flight.clone();
+            tokio::spawn(async move {
+                if let Err(why) = store.put(&key, to_store).await {
+                    log::error!("fail to store media {}, {:?}", key, why);
+                }
+                Self::land(&in_flight, &key, &flight);
+            });
+            return Ok(Box::new(tapped));
+        }
+    }
+
+    async fn is_rendered(&self, origin: &MediaOrigin) -> bool {
+        let key = self.creator.cache_key(origin);
+        if self.in_flight.lock().unwrap().contains_key(&key) {
+            return true;
+        }
+        self.store.get(&key).await.is_ok()
+    }
+
+    async fn upload(&self, upload: &Upload, data: &[u8]) -> Result<(), UploadError> {
+        self.creator.upload(upload, data).await
+    }
+
+    async fn probe(&self, source: &Source) -> Result<SourceMetadata, ProbeError> {
+        self.creator.probe(source).await
+    }
+}
+
+impl<C, S> CachedCreator<C, S>
+where
+    C: Creator,
+    S: Store,
+{
+    pub fn store(&self) -> Arc<S> {
+        self.store.clone()
+    }
+
+    /// `ffmpeg` migrates entries cached in older formats.
+    pub fn new(creator: C, store: S, ffmpeg: ExternalTool) -> Self {
+        Self {
+            creator: Arc::new(creator),
+            store: Arc::new(store),
+            ffmpeg,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Keys the media of `origin` was cached under before it was stored as [`STORAGE_FORMAT`],
+    /// or before the parts of composites were keyed by their creators.
+    pub fn predecessor_keys(&self, origin: &MediaOrigin) -> Vec<String> {
+        let current = self.creator.signature(origin);
+        let signature = CreatorSignature {
+            format: Format::Mp3.name(),
+            ..current
+        };
+        let mut keys = vec![origin.cache_key(&signature)];
+        keys.extend(origin.legacy_cache_key(&signature));
+        if matches!(origin.source, Source::Composite { .. }) {
+            keys.push(origin.cache_key(&current));
+        }
+        keys
+    }
+
+    /// Transcode the entry of `origin` cached in an older format, if there is one. The old entry
+    /// is left for the cache maintenance to collect.
+    async fn migrate(&self, origin: &MediaOrigin) -> Option<ProcessPipeline>
+    where
+        S::Output: Send + Unpin + 'static,
+    {
+        for key in self.predecessor_keys(origin) {
+            let media = match self.store.get(&key).await {
+                Ok(media) => media,
+                Err(StoreGetError::NotFound) => continue,
+                Err(why) => {
+                    log::warn!("fail to read cache entry {}, {:?}", key, why);
+                    continue;
+                }
+            };
+            match transcode(&self.ffmpeg, media, STORAGE_FORMAT).await {
+                Ok(output) => {
+                    log::info!("migrating cache entry {}", key);
+                    return Some(output);
+                }
+                Err(why) => log::error!("fail to migrate cache entry {}, {}", key, why),
+            }
+        }
+        None
+    }
+
+    fn join_flight(&self, key: &str) -> InFlight {
+        self.in_flight
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_insert_with(|| Arc::new(tokio::sync::Mutex::new(Flight::Pending)))
+            .clone()
+    }
+
+    fn land(in_flight: &Mutex<HashMap<String, InFlight>>, key: &str, flight: &InFlight) {
+        let mut in_flight = in_flight.lock().unwrap();
+        if let Some(current) = in_flight.get(key) {
+            if Arc::ptr_eq(current, flight) {
+                in_flight.remove(key);
+            }
+        }
+    }
+
+    /// Pull the pipeline to the end so taps keep receiving data even if some readers stop early.
+    async fn drain(mut pipeline: TappableReader<Box<dyn AsyncRead + Send + Unpin>>) {
+        let mut buf = [0; 8192];
+        loop {
+            match pipeline.read(&mut buf).await {
+                Ok(0) => break,
+                Ok(_) => (),
+                Err(why) if why.kind() == io::ErrorKind::Interrupted => (),
+                Err(why) => {
+                    log::error!("fail to create media, {:?}", why);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Media shown around the cut in the waveform of a preview.
+const WAVEFORM_CONTEXT: Duration = Duration::from_secs(10);
+
+/// Sound kept around the silence trimmed off, so the onset isn't cut and the delay of the mp3
+/// decoder doesn't count as silence.
+const TRIM_MARGIN: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PreviewOptions {
+    /// draw the media around the cut in the waveform
+    pub context: bool,
+    /// move the cut past silence at both ends before rendering
+    pub trim_silence: bool,
+}
+
+/// Silence trimmed off the start and the end of a cut.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Trimmed {
+    pub leading: Duration,
+    pub trailing: Duration,
+}
+
+pub struct PreviewingFx {
+    /// mp3, whatever the storage format is
+    pub media: Vec<u8>,
+    /// PNG of the waveform, missing if it couldn't be drawn
+    pub waveform: Option<Vec<u8>>,
+    /// what was trimmed if trimming was asked for
+    pub trimmed: Option<Trimmed>,
+    pub fx: Fx,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FxIdentity(pub GuildId, pub String);
+
+pub struct FxWithMedia<M>(pub Fx, pub M);
+
+/// Media of an fx to play.
+pub enum Playable<M> {
+    /// the fx has been played recently
+    Decoded(DecodedMedia),
+    Stream(M),
+}
+
+#[derive(Debug)]
+pub enum GetFxError<C> {
+    Repository(RepositoryGetError),
+    Create(C),
+    Queue(QueueError),
+}
+
+/// Failure while rendering media, either before it starts or while it's being read.
+#[derive(Debug)]
+pub enum RenderError<C> {
+    Create(C),
+    Queue(QueueError),
+    /// the media couldn't be converted to the format of previews
+    Export(StageFailure),
+    Media(io::Error),
+}
+
+impl<C: Display> Display for RenderError<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::Create(why) => write!(f, "{}", why),
+            RenderError::Queue(why) => write!(f, "{}", why),
+            RenderError::Export(why) => write!(f, "{}", why),
+            RenderError::Media(why) => write!(f, "{}", why),
+        }
+    }
+}
+
+/// The cut ends after the source does.
+#[derive(Debug)]
+pub struct PastEndError {
+    pub duration: Duration,
+}
+
+impl Display for PastEndError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the cut ends after the source, which is {:.1}s long",
+            self.duration.as_secs_f64()
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum ComposeError {
+    Parse(CompositeParseError),
+    FxNotFound(String),
+    Repository(RepositoryGetError),
+}
+
+impl Display for ComposeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ComposeError::Parse(why) => write!(f, "{}", why),
+            ComposeError::FxNotFound(name) => write!(f, "fx `{}` not found", name),
+            ComposeError::Repository(why) => write!(f, "{:?}", why),
+        }
+    }
+}
+
+pub struct Controller<C, R>
+where
+    C: Creator,
+    R: Repository,
+{
+    creator: Arc<C>,
+    repository: Arc<R>,
+    ffmpeg: ExternalTool,
+    trim: config::Trim,
+    hot: Arc<Mutex<HotCache>>,
+    queue: Arc<RenderQueue>,
+}
+
+impl<C, R> Controller<C, R>
+where
+    C: Creator,
+    R: Repository,
+{
+    /// `ffmpeg` exports previews to mp3.
+    pub fn new(
+        creator: C,
+        repository: R,
+        ffmpeg: ExternalTool,
+        trim: config::Trim,
+        hot_cache: config::HotCache,
+        queue: config::Queue,
+    ) -> Self {
+        Self {
+            creator: Arc::new(creator),
+            repository: Arc::new(repository),
+            ffmpeg,
+            trim,
+            hot: Arc::new(Mutex::new(HotCache::new(
+                hot_cache.budget_mib * 1024 * 1024,
+            ))),
+            queue: Arc::new(RenderQueue::new(queue)),
+        }
+    }
+
+    pub fn creator(&self) -> Arc<C> {
+        self.creator.clone()
+    }
+
+    pub fn repository(&self) -> Arc<R> {
+        self.repository.clone()
+    }
+
+    pub fn queue(&self) -> Arc<RenderQueue> {
+        self.queue.clone()
+    }
+    /// Render `origin` as mp3.
+    async fn render(
+        &self,
+        origin: &MediaOrigin,
+        progress: &ProgressReporter,
+    ) -> Result<Vec<u8>, RenderError<C::Error>> {
+        let media = self
+            .creator
+            .create_reporting(origin, progress)
+            .await
+            .map_err(RenderError::Create)?;
+        progress.report(Progress::Cutting);
+        let mut output = transcode(&self.ffmpeg, media, Format::Mp3)
+            .await
+            .map_err(RenderError::Export)?;
+        let mut buf = vec![];
+        output
+            .read_to_end(&mut buf)
+            .await
+            .map_err(RenderError::Media)?;
+        Ok(buf)
+    }
+
+    /// Render the fx for previewing, once its turn in the render queue comes. With `context`, the
+    /// waveform also shows the media around the cut when the source has any.
+    pub async fn init_create_fx(
+        &self,
+        fx: Fx,
+        options: PreviewOptions,
+        progress: &ProgressReporter,
+    ) -> Result<PreviewingFx, RenderError<C::Error>> {
+        let owner = JobOwner {
+            guild: fx.discord.guild,
+            user: fx.discord.author,
+        };
+        let _job = self
+            .queue
+            .join(owner, progress)
+            .await
+            .map_err(RenderError::Queue)?;
+        let (fx, trimmed) = if options.trim_silence {
+            let (fx, trimmed) = self.trim_silence(fx, progress).await?;
+            (fx, Some(trimmed))
+        } else {
+            (fx, None)
+        };
+        let media = self.render(&fx.media, progress).await?;
+        let waveform = self
+            .waveform(&fx.media, &media, options.context, progress)
+            .await;
+        Ok(PreviewingFx {
+            fx,
+            media,
+            waveform,
+            trimmed,
+        })
+    }
+
+    /// Move the cut past the silence at both ends, judged on the cut without effects so the
+    /// result maps back to the source. Sources that can't be cut are left as they are.
+    async fn trim_silence(
+        &self,
+        fx: Fx,
+        progress: &ProgressReporter,
+    ) -> Result<(Fx, Trimmed), RenderError<C::Error>> {
+        if !fx.media.can_cut() {
+            return Ok((fx, Trimmed::default()));
+        }
+        let plain = MediaOrigin {
+            effects: vec![],
+            ..fx.media.clone()
+        };
+        let media = self.render(&plain, progress).await?;
+        let threshold_db = self.trim.silence_threshold_db;
+        let silence = tokio::task::spawn_blocking(move || {
+            waveform::decode_mp3(media).map(|samples| samples.silence(threshold_db))
+        })
+        .await;
+        let (leading, trailing) = match silence {
+            Ok(Ok(silence)) => silence,
+            Ok(Err(why)) => {
+                log::warn!(
+                    "fail to find the silence of {:?}, {:?}",
+                    fx.media.source,
+                    why
+                );
+                return Ok((fx, Trimmed::default()));
+            }
+            Err(why) => {
+                log::error!(
+                    "fail to find the silence of {:?}, {:?}",
+                    fx.media.source,
+                    why
+                );
+                return Ok((fx, Trimmed::default()));
+            }
+        };
+        // whole milliseconds, as cuts are made
+        let millis = |duration: Duration| {
+            Duration::from_millis(duration.saturating_sub(TRIM_MARGIN).as_millis() as u64)
+        };
+        let trimmed = Trimmed {
+            leading: millis(leading),
+            trailing: millis(trailing),
+        };
+        let media = MediaOrigin {
+            start: fx.media.start + trimmed.leading,
+            length: fx
+                .media
+                .length
+                .saturating_sub(trimmed.leading + trimmed.trailing),
+            ..fx.media
+        };
+        Ok((Fx { media, ..fx }, trimmed))
+    }
+
+    async fn waveform(
+        &self,
+        origin: &MediaOrigin,
+        media: &[u8],
+        context: bool,
+        progress: &ProgressReporter,
+    ) -> Option<Vec<u8>> {
+        let (media, selected) = match origin.context(WAVEFORM_CONTEXT).filter(|_| context) {
+            Some((context, selected)) => match self.render(&context, progress).await {
+                Ok(media) => (media, Some(selected)),
+                Err(why) => {
+                    log::warn!("fail to render the context of {:?}, {}", origin.source, why);
+                    (media.to_vec(), None)
+                }
+            },
+            None => (media.to_vec(), None),
+        };
+        let waveform = tokio::task::spawn_blocking(move || {
+            waveform::decode_mp3(media).and_then(|samples| waveform::render(&samples, selected))
+        })
+        .await;
+        match waveform {
+            Ok(Ok(png)) => Some(png),
+            Ok(Err(why)) => {
+                log::warn!("fail to draw the waveform, {:?}", why);
+                None
+            }
+            Err(why) => {
+                log::error!("fail to draw the waveform, {:?}", why);
+                None
+            }
+        }
+    }
+
+    /// Attach the details of the source to the draft, rejecting cuts past its end. Details are
+    /// only nice to have, so failing to look them up leaves the draft as is.
+    pub async fn describe(&self, fx: Fx) -> Result<Fx, PastEndError> {
+        let metadata = match self.creator.probe(&fx.media.source).await {
+            Ok(metadata) => metadata,
+            Err(ProbeError::Unsupported) => return Ok(fx),
+            Err(why) => {
+                log::warn!("fail to look up {}, {}", fx.media.source, why);
+                return Ok(fx);
+            }
+        };
+        if !metadata.contains(&fx.media) {
+            return Err(PastEndError {
+                duration: metadata.duration.unwrap_or_default(),
+            });
+        }
+        Ok(Fx {
+            metadata: Some(metadata),
+            ..fx
+        })
+    }
+
+    /// Origin of the composite written as `spec`, fx in it are looked up in the guild and copied.
+    pub async fn compose(&self, guild: GuildId, spec: &str) -> Result<MediaOrigin, ComposeError> {
+        let specs = composite::parse_segments(spec).map_err(ComposeError::Parse)?;
+        let mut segments = vec![];
+        let mut length = Duration::ZERO;
+        for SegmentSpec { part, join } in specs {
+            let (origin, fx) = match part {
+                PartSpec::Fx(name) => {
+                    match self.repository.get(&FxIdentity(guild, name.clone())).await {
+                        Ok(fx) => (fx.media, Some(name)),
+                        Err(RepositoryGetError::NotFound) => {
+                            return Err(ComposeError::FxNotFound(name))
+                        }
+                        Err(why) => return Err(ComposeError::Repository(why)),
+                    }
+                }
+                part => (part.to_origin().unwrap(), None),
+            };
+            length = match join {
+                Join::Cut => length,
+                Join::Gap { millis } => length + Duration::from_millis(millis as u64),
+                Join::Crossfade { millis } => {
+                    length.saturating_sub(Duration::from_millis(millis as u64))
+                }
+            } + origin.length;
+            segments.push(Segment { origin, fx, join });
+        }
+        Ok(MediaOrigin {
+            source: Source::Composite { segments },
+            start: Duration::ZERO,
+            length,
+            effects: vec![],
+        })
+    }
+
+    pub async fn confirm_create(&self, fx: Fx) -> Result<(), RepositoryAddError> {
+        if let Some(guild) = fx.discord.guild {
+            let identity = FxIdentity(guild, fx.name.clone());
+            self.hot.lock().unwrap().invalidate(&identity);
+        }
+        self.repository.add(fx).await
+    }
+
+    /// Media of a draft to listen to before confirming it, the draft is kept as it is. Media
+    /// that has to be rendered waits for its turn in the render queue first, and the job runs
+    /// while the media is streamed.
+    pub async fn draft_media(
+        &self,
+        fx: &Fx,
+        owner: JobOwner,
+        progress: &ProgressReporter,
+    ) -> Result<QueuedMedia<C::Output>, RenderError<C::Error>> {
+        let job = if self.creator.is_rendered(&fx.media).await {
+            None
+        } else {
+            let job = self
+                .queue
+                .join(owner, progress)
+                .await
+                .map_err(RenderError::Queue)?;
+            Some(job)
+        };
+        let media = self
+            .creator
+            .create(&fx.media)
+            .await
+            .map_err(RenderError::Create)?;
+        Ok(QueuedMedia::new(media, job))
+    }
+    /// Get the fx along with its media, which is streamed while it's being created.
+    pub async fn get(
+        &self,
+        identity: &FxIdentity,
+    ) -> Result<FxWithMedia<C::Output>, GetFxError<C::Error>> {
+        let fx = self
+            .repository
+            .get(identity)
+            .await
+            .map_err(GetFxError::Repository)?;
+        let media = self
+            .creator
+            .create(&fx.media)
+            .await
+            .map_err(GetFxError::Create)?;
+        Ok(FxWithMedia(fx, media))
+    }
+
+    /// Get the fx along with its media to play for `player`. Media that isn't decoded yet is
+    /// streamed, and decoded in the background so that playing the fx again starts at once.
+    /// Media that has to be rendered waits for its turn in the render queue first.
+    pub async fn play(
+        &self,
+        identity: &FxIdentity,
+        player: UserId,
+        progress: &ProgressReporter,
+    ) -> Result<FxWithMedia<Playable<C::Output>>, GetFxError<C::Error>> {
+        let fx = self
+            .repository
+            .get(identity)
+            .await
+            .map_err(GetFxError::Repository)?;
+        let key = self.creator.cache_key(&fx.media);
+        if let Some(media) = self.hot.lock().unwrap().get(identity, &key) {
+            return Ok(FxWithMedia(fx, Playable::Decoded(media)));
+        }
+        let job = if self.creator.is_rendered(&fx.media).await {
+            None
+        } else {
+            let owner = JobOwner {
+                guild: Some(identity.0),
+                user: Some(player),
+            };
+            let job = self
+                .queue
+                .join(owner, progress)
+                .await
+                .map_err(GetFxError::Queue)?;
+            Some(job)
+        };
+        let media = self
+            .creator
+            .create(&fx.media)
+            .await
+            .map_err(GetFxError::Create)?;
+        self.warm(identity, &fx.media, key, job);
+        Ok(FxWithMedia(fx, Playable::Stream(media)))
+    }
+
+    /// Decode the media of the fx into the hot cache. It's created again rather than tapped
+    /// from the stream being played, which may be stopped before its end. Decoding reads the
+    /// render to its end, so `job` is held until then.
+    fn warm(
+        &self,
+        identity: &FxIdentity,
+        origin: &MediaOrigin,
+        key: String,
+        job: Option<JobPermit>,
+    ) {
+        if !self.hot.lock().unwrap().start_warming(identity) {
+            return;
+        }
+        let creator = self.creator.clone();
+        let hot = self.hot.clone();
+        let identity = identity.clone();
+        let origin = origin.clone();
+        tokio::spawn(async move {
+            let decoded = match creator.create(&origin).await {
+                Ok(media) => audio::decode_media_stream(media).await.ok(),
+                Err(why) => {
+                    log::error!("fail to create {} for decoding, {:?}", origin.source, why);
+                    None
+                }
+            };
+            drop(job);
+            let mut hot = hot.lock().unwrap();
+            match decoded {
+                Some(media) => hot.insert(identity, key, media),
+                None => hot.stop_warming(&identity),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    fn origin(url: &str, start: u64, length: u64) -> MediaOrigin {
+        MediaOrigin {
+            source: Source::Url {
+                url: url.to_string(),
+            },
+            start: Duration::from_secs(start),
+            length: Duration::from_secs(length),
+            effects: vec![],
+        }
+    }
+
+    #[test]
+    fn test_nudge_stays_in_bounds() {
+        let origin = origin("https://youtu.be/x", 0, 20);
+        assert_eq!(Duration::ZERO, origin.nudged(Nudge::Start(-1000)).start);
+        assert_eq!(
+            Duration::from_millis(100),
+            origin.nudged(Nudge::Start(100)).start
+        );
+        assert_eq!(MAX_LENGTH, origin.nudged(Nudge::Length(500)).length);
+        let origin = origin.nudged(Nudge::Length(-19_950));
+        assert_eq!(MIN_LENGTH, origin.length);
+    }
+
+    #[test]
+    fn test_cache_key_is_stable() {
+        let signature = YoutubeDLCreator::SIGNATURE;
+        assert_eq!(
+            origin("https://youtu.be/a", 1, 5).cache_key(&signature),
+            origin("https://youtu.be/a", 1, 5).cache_key(&signature),
+        );
+    }
+
+    fn speech(text: &str, voice: Option<&str>) -> MediaOrigin {
+        MediaOrigin {
+            source: Source::Speech {
+                speech: Speech {
+                    text: text.to_string(),
+                    voice: voice.map(str::to_string),
+                },
+            },
+            start: Duration::ZERO,
+            length: Duration::from_secs(5),
+            effects: vec![],
+        }
+    }
+
+    // each pair concatenates to the same fields, split at a different place
+    #[test_case(speech("hello", Some("en")), speech("hell", Some("oen")); "text and voice")]
+    #[test_case(speech("hello", Some("en")), speech("helloen", None); "text and missing voice")]
+    fn test_cache_key_has_no_ambiguous_boundaries(left: MediaOrigin, right: MediaOrigin) {
+        let signature = YoutubeDLCreator::SIGNATURE;
+        assert_ne!(left.cache_key(&signature), right.cache_key(&signature));
+    }
+
+    #[test]
+    fn test_cache_key_depends_on_signature() {
+        let origin = origin("https://youtu.be/a", 1, 5);
+        let bumped = CreatorSignature {
+            version: YoutubeDLCreator::SIGNATURE.version + 1,
+            ..YoutubeDLCreator::SIGNATURE
+        };
+        assert_ne!(
+            origin.cache_key(&YoutubeDLCreator::SIGNATURE),
+            origin.cache_key(&bumped)
+        );
+        assert!(origin.legacy_cache_key(&bumped).is_none());
+    }
+
+    #[test_case(origin("https://youtu.be/a", 1, 5), true; "whole seconds")]
+    #[test_case(MediaOrigin { effects: vec![Effect::Reverse], ..origin("https://youtu.be/a", 1, 5) }, false; "effect")]
+    #[test_case(MediaOrigin { start: Duration::from_millis(1100), ..origin("https://youtu.be/a", 1, 5) }, false; "start off whole seconds")]
+    fn test_legacy_cache_key_only_of_plain_cuts(origin: MediaOrigin, keyed: bool) {
+        assert_eq!(keyed, origin.legacy_cache_key(&LEGACY_SIGNATURE).is_some());
+    }
+
+    #[test]
+    fn test_composite_cache_key_depends_on_part_creators() {
+        let part = origin("https://youtu.be/a", 1, 5);
+        let composite = MediaOrigin {
+            source: Source::Composite {
+                segments: vec![Segment {
+                    origin: part.clone(),
+                    fx: Some("meow".to_string()),
+                    join: Join::Cut,
+                }],
+            },
+            start: Duration::ZERO,
+            length: part.length,
+            effects: vec![],
+        };
+        let bumped = CreatorSignature {
+            version: YoutubeDLCreator::SIGNATURE.version + 1,
+            ..YoutubeDLCreator::SIGNATURE
+        };
+        let signature = MediaCreator::<LocalStore>::COMPOSITE_SIGNATURE;
+        assert_ne!(
+            composite
+                .composite_cache_key(&signature, &[part.cache_key(&YoutubeDLCreator::SIGNATURE)]),
+            composite.composite_cache_key(&signature, &[part.cache_key(&bumped)]),
+        );
+    }
+
+    #[test]
+    fn test_origin_without_source_kind_reads_as_url() {
+        let legacy = doc! {
+            "url": "https://youtu.be/a",
+            "start": { "secs": 1_i64, "nanos": 0_i32 },
+            "length": { "secs": 5_i64, "nanos": 0_i32 },
+        };
+        let origin: MediaOrigin = mongodb::bson::from_document(legacy).unwrap();
+        assert!(matches!(origin.source, Source::Url { url } if url == "https://youtu.be/a"));
+    }
+
+    #[test]
+    fn test_attachment_origin_round_trip() {
+        let origin = MediaOrigin {
+            source: Source::Attachment {
+                attachment: Upload {
+                    id: AttachmentId(42),
+                    filename: "meow.ogg".to_string(),
+                    url: "https://cdn.discordapp.com/meow.ogg".to_string(),
+                },
+            },
+            start: Duration::from_secs(1),
+            length: Duration::from_secs(5),
+            effects: vec![],
+        };
+        let document = mongodb::bson::to_document(&origin).unwrap();
+        let origin: MediaOrigin = mongodb::bson::from_document(document).unwrap();
+        assert_eq!(vec!["upload-42".to_string()], origin.upload_keys());
+    }
+
+    /// Tools of the host for tests running ffmpeg, which are skipped without it.
+    fn host_tools() -> Option<Toolchain> {
+        let tool = |program: &str| ExternalTool {
+            program: program.to_string(),
+            args: vec![],
+            timeout: Duration::from_secs(30),
+        };
+        let ffmpeg = tool("ffmpeg");
+        match ffmpeg.std_command().arg("-version").output() {
+            Ok(output) if output.status.success() => Some(Toolchain {
+                downloader_kind: config::Downloader::YtDlp,
+                downloader: tool("yt-dlp"),
+                ffmpeg,
+                speech: None,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Types of the top-level boxes of an MP4 file, in order.
+    fn mp4_boxes(file: &[u8]) -> Vec<String> {
+        let mut boxes = vec![];
+        let mut offset = 0;
+        while offset + 8 <= file.len() {
+            let size = u32::from_be_bytes(file[offset..offset + 4].try_into().unwrap()) as usize;
+            boxes.push(String::from_utf8_lossy(&file[offset + 4..offset + 8]).to_string());
+            if size < 8 {
+                break;
+            }
+            offset += size;
+        }
+        boxes
+    }
+
+    /// Directory in the system temp dir, removed when dropped even if the test fails.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("huahua-{:08x}", rand::random::<u32>()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _result = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_attachment_with_index_at_the_end() {
+        // the media is made and cut by ffmpeg, there's nothing to test without it
+        let tools = match host_tools() {
+            Some(tools) => tools,
+            None => return,
+        };
+        let temp = TempDir::new();
+        let dir = &temp.0;
+        let upload = Upload {
+            id: AttachmentId(42),
+            filename: "clip.mp4".to_string(),
+            url: "https://cdn.discordapp.com/clip.mp4".to_string(),
+        };
+        // ffmpeg writes the index of MP4 files after the media unless told to move it
+        let status = tools
+            .ffmpeg
+            .command()
+            .args(["-hide_banner", "-loglevel", "error"])
+            .args([
+                "-f",
+                "lavfi",
+                "-i",
+                "sine=duration=3",
+                "-c:a",
+                "aac",
+                "-f",
+                "mp4",
+            ])
+            .arg(dir.join(upload.key()))
+            .status()
+            .await
+            .unwrap();
+        assert!(status.success());
+        let boxes = mp4_boxes(&fs::read(dir.join(upload.key())).await.unwrap());
+        let position = |kind: &str| boxes.iter().position(|found| found == kind).unwrap();
+        assert!(position("moov") > position("mdat"));
+
+        let creator = AttachmentCreator::new(LocalStore::new(dir), tools);
+        let origin = MediaOrigin {
+            source: Source::Attachment { attachment: upload },
+            start: Duration::from_secs(1),
+            length: Duration::from_secs(1),
+            effects: vec![],
+        };
+        let created = match creator.create(&origin).await {
+            Ok(mut media) => {
+                let mut output = vec![];
+                media.read_to_end(&mut output).await.map(|_| output)
+            }
+            Err(why) => panic!("{:?}", why),
+        };
+        assert!(!created.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cache_key_depends_on_voice() {
+        let speech = |voice: Option<&str>| MediaOrigin {
+            source: Source::Speech {
+                speech: Speech {
+                    text: "喵".to_string(),
+                    voice: voice.map(str::to_string),
+                },
+            },
+            start: Duration::ZERO,
+            length: Duration::from_secs(20),
+            effects: vec![],
+        };
+        let signature = SpeechCreator::SIGNATURE;
+        assert_ne!(
+            speech(None).cache_key(&signature),
+            speech(Some("zh")).cache_key(&signature)
+        );
+    }
+
+    #[test]
+    fn test_cache_key_depends_on_effects() {
+        let plain = origin("https://youtu.be/a", 1, 5);
+        let mut reversed = plain.clone();
+        reversed.effects.push(Effect::Reverse);
+        assert_ne!(
+            plain.cache_key(&YoutubeDLCreator::SIGNATURE),
+            reversed.cache_key(&YoutubeDLCreator::SIGNATURE)
+        );
+    }
+
+    #[test]
+    fn test_metadata_from_downloader_dump() {
+        let dump = r#"{"id": "x", "title": "Purr", "uploader": "Cat", "duration": 12.5,
+            "thumbnail": "https://i.ytimg.com/x.jpg", "formats": []}"#;
+        let info: DownloaderInfo = serde_json::from_str(dump).unwrap();
+        let metadata = SourceMetadata::from(info);
+        assert_eq!(Some("Purr".to_string()), metadata.title);
+        assert_eq!(Some(Duration::from_millis(12_500)), metadata.duration);
+        assert!(metadata.contains(&origin("https://youtu.be/x", 7, 5)));
+        assert!(!metadata.contains(&origin("https://youtu.be/x", 8, 5)));
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display},
+    future::Future,
+    io,
+    pin::Pin,
+    process::ExitStatus,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, ReadBuf},
+    process::{Child, ChildStderr, ChildStdout},
+    sync::{mpsc::UnboundedSender, oneshot},
+    task::JoinHandle,
+};
+
+const STDERR_TAIL_CAPACITY: usize = 4096;
+const STDERR_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub enum StageFailure {
+    Exited {
+        stage: &'static str,
+        status: ExitStatus,
+        stderr: String,
+    },
+    TimedOut {
+        stage: &'static str,
+        timeout: Duration,
+        stderr: String,
+    },
+    IO {
+        stage: &'static str,
+        error: io::Error,
+    },
+}
+
+fn last_line(stderr: &str) -> &str {
+    stderr
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_default()
+}
+
+impl Display for StageFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StageFailure::Exited {
+                stage,
+                status,
+                stderr,
+            } => write!(f, "{} failed ({}): {}", stage, status, last_line(stderr)),
+            StageFailure::TimedOut {
+                stage,
+                timeout,
+                stderr,
+            } => write!(
+                f,
+                "{} timed out after {}s: {}",
+                stage,
+                timeout.as_secs(),
+                last_line(stderr)
+            ),
+            StageFailure::IO { stage, error } => write!(f, "{}: {}", stage, error),
+        }
+    }
+}
+
+impl std::error::Error for StageFailure {}
+
+/// Keeps the last bytes a child writes to its stderr.
+#[derive(Clone, Default)]
+struct StderrTail(Arc<Mutex<VecDeque<u8>>>);
+
+impl StderrTail {
+    /// Capture `stderr`, also sending each line of it to `lines`.
+    fn capture(
+        &self,
+        mut stderr: ChildStderr,
+        lines: Option<UnboundedSender<String>>,
+    ) -> JoinHandle<()> {
+        let tail = self.clone();
+        tokio::spawn(async move {
+            let mut buf = [0; 1024];
+            let mut line = vec![];
+            while let Ok(n) = stderr.read(&mut buf).await {
+                if n == 0 {
+                    break;
+                }
+                tail.push(&buf[..n]);
+                let lines = match lines.as_ref() {
+                    Some(lines) => lines,
+                    None => continue,
+                };
+                // progress is often redrawn with carriage returns rather than new lines
+                for byte in &buf[..n] {
+                    if *byte == b'\n' || *byte == b'\r' {
+                        if !line.is_empty() {
+                            let _result = lines.send(String::from_utf8_lossy(&line).to_string());
+                            line.clear();
+                        }
+                    } else {
+                        line.push(*byte);
+                    }
+                }
+            }
+            if let Some(lines) = lines.filter(|_| !line.is_empty()) {
+                let _result = lines.send(String::from_utf8_lossy(&line).to_string());
+            }
+        })
+    }
+
+    fn push(&self, data: &[u8]) {
+        let mut tail = self.0.lock().unwrap();
+        tail.extend(data);
+        let excess = tail.len().saturating_sub(STDERR_TAIL_CAPACITY);
+        tail.drain(..excess);
+    }
+
+    fn text(&self) -> String {
+        let tail = self.0.lock().unwrap();
+        String::from_utf8_lossy(&tail.iter().copied().collect::<Vec<u8>>())
+            .trim()
+            .to_string()
+    }
+}
+
+pub struct Stage {
+    pub name: &'static str,
+    pub child: Child,
+    pub timeout: Duration,
+    /// receives the lines the child writes to its stderr
+    pub stderr_lines: Option<UnboundedSender<String>>,
+}
+
+struct StageHandle {
+    outcome: oneshot::Receiver<Result<(), StageFailure>>,
+    // dropping it kills the child if it is still running
+    _cancel: oneshot::Sender<()>,
+}
+
+enum Event {
+    Exited(io::Result<ExitStatus>),
+    TimedOut,
+    Cancelled,
+}
+
+/// Wait for the child of a stage, killing it once its timeout elapses or the stage is cancelled.
+async fn supervise(
+    mut stage: Stage,
+    mut cancel: oneshot::Receiver<()>,
+    report: oneshot::Sender<Result<(), StageFailure>>,
+) {
+    let tail = StderrTail::default();
+    let lines = stage.stderr_lines.take();
+    let capture = stage
+        .child
+        .stderr
+        .take()
+        .map(|stderr| tail.capture(stderr, lines));
+    let event = tokio::select! {
+        status = stage.child.wait() => Event::Exited(status),
+        _ = tokio::time::sleep(stage.timeout) => Event::TimedOut,
+        _ = &mut cancel => Event::Cancelled,
+    };
+    if !matches!(event, Event::Exited(Ok(_))) {
+        if let Err(why) = stage.child.kill().await {
+            log::error!("fail to kill {}, {:?}", stage.name, why);
+        }
+    }
+    if let Some(capture) = capture {
+        let _result = tokio::time::timeout(STDERR_DRAIN_TIMEOUT, capture).await;
+    }
+    let outcome = match event {
+        Event::Exited(Ok(status)) if status.success() => Ok(()),
+        Event::Exited(Ok(status)) => Err(StageFailure::Exited {
+            stage: stage.name,
+            status,
+            stderr: tail.text(),
+        }),
+        Event::Exited(Err(error)) => Err(StageFailure::IO {
+            stage: stage.name,
+            error,
+        }),
+        Event::TimedOut => Err(StageFailure::TimedOut {
+            stage: stage.name,
+            timeout: stage.timeout,
+            stderr: tail.text(),
+        }),
+        Event::Cancelled => return,
+    };
+    if let Err(Err(failure)) = report.send(outcome) {
+        log::debug!(
+            "{} failed after its output was dropped, {:?}",
+            stage.name,
+            failure
+        );
+    }
+}
+
+/// Output of a chain of child processes, read from the stdout of the last stage. Every stage is
+/// killed when its timeout elapses or when the output is dropped, and reaped in both cases. The
+/// output only ends cleanly if the last stage succeeded, otherwise reading fails with the
+/// [`StageFailure`] most likely to be the cause.
+pub struct ProcessPipeline {
+    stdout: ChildStdout,
+    last_stage: &'static str,
+    peeked: Option<Vec<u8>>,
+    stages: Vec<StageHandle>,
+    finished: bool,
+}
+
+impl ProcessPipeline {
+    /// The stages must be chained already, and the last one must have a piped stdout.
+    pub fn new(mut stages: Vec<Stage>) -> Self {
+        let last = stages.last_mut().expect("a pipeline needs a stage");
+        let stdout = last
+            .child
+            .stdout
+            .take()
+            .expect("stdout of the last stage must be piped");
+        let last_stage = last.name;
+        let stages = stages
+            .into_iter()
+            .map(|stage| {
+                let (cancel, cancelled) = oneshot::channel();
+                let (report, outcome) = oneshot::channel();
+                tokio::spawn(supervise(stage, cancelled, report));
+                StageHandle {
+                    outcome,
+                    _cancel: cancel,
+                }
+            })
+            .collect();
+        Self {
+            stdout,
+            last_stage,
+            peeked: None,
+            stages,
+            finished: false,
+        }
+    }
+
+    /// Wait until the pipeline produces output, so failures of the first moments are reported
+    /// here rather than while reading.
+    pub async fn ready(&mut self) -> Result<(), StageFailure> {
+        let mut chunk = vec![0; 8192];
+        let n = futures::future::poll_fn(|cx| {
+            let mut buf = ReadBuf::new(&mut chunk);
+            self.poll_next(cx, &mut buf).map_ok(|()| buf.filled().len())
+        })
+        .await?;
+        chunk.truncate(n);
+        self.peeked = Some(chunk);
+        Ok(())
+    }
+
+    fn poll_next(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<(), StageFailure>> {
+        if let Some(peeked) = self.peeked.as_mut() {
+            if !peeked.is_empty() {
+                let n = peeked.len().min(buf.remaining());
+                buf.put_slice(&peeked[..n]);
+                peeked.drain(..n);
+                return Poll::Ready(Ok(()));
+            }
+            self.peeked = None;
+        }
+        if self.finished {
+            return Poll::Ready(Ok(()));
+        }
+        let filled = buf.filled().len();
+        match Pin::new(&mut self.stdout).poll_read(cx, buf) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(Err(error)) => {
+                return Poll::Ready(Err(StageFailure::IO {
+                    stage: self.last_stage,
+                    error,
+                }))
+            }
+            Poll::Ready(Ok(())) => (),
+        }
+        if buf.filled().len() > filled || buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        // the output ended, it's complete only if the last stage succeeded
+        let last = self.stages.last_mut().unwrap();
+        let outcome = match Pin::new(&mut last.outcome).poll(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(outcome) => outcome,
+        };
+        self.finished = true;
+        let stages = std::mem::take(&mut self.stages);
+        match outcome {
+            Ok(Ok(())) | Err(_) => Poll::Ready(Ok(())),
+            Ok(Err(failure)) => {
+                // a failed upstream stage usually explains why the last one failed
+                let cause =
+                    stages
+                        .into_iter()
+                        .find_map(|mut stage| match stage.outcome.try_recv() {
+                            Ok(Err(failure)) => Some(failure),
+                            _ => None,
+                        });
+                Poll::Ready(Err(cause.unwrap_or(failure)))
+            }
+        }
+    }
+}
+
+impl AsyncRead for ProcessPipeline {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.get_mut()
+            .poll_next(cx, buf)
+            .map_err(|failure| io::Error::new(io::ErrorKind::Other, failure))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{process::Stdio, time::Duration};
+    use tokio::{io::AsyncReadExt, process::Command};
+
+    use super::{ProcessPipeline, Stage, StageFailure};
+
+    fn shell(script: &str, timeout: Duration) -> Stage {
+        let child = Command::new("sh")
+            .args(["-c", script])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        Stage {
+            name: "sh",
+            child,
+            timeout,
+            stderr_lines: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_successful_stage() {
+        let mut pipeline =
+            ProcessPipeline::new(vec![shell("printf hello", Duration::from_secs(5))]);
+        pipeline.ready().await.unwrap();
+        let mut output = vec![];
+        pipeline.read_to_end(&mut output).await.unwrap();
+        assert_eq!(b"hello".to_vec(), output);
+    }
+
+    #[tokio::test]
+    async fn test_failed_stage_keeps_stderr() {
+        let mut pipeline = ProcessPipeline::new(vec![shell(
+            "echo noise >&2; echo 'no such video' >&2; exit 3",
+            Duration::from_secs(5),
+        )]);
+        match pipeline.ready().await {
+            Err(StageFailure::Exited { status, stderr, .. }) => {
+                assert_eq!(Some(3), status.code());
+                assert_eq!("noise\nno such video", stderr);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stage_timeout() {
+        let mut pipeline =
+            ProcessPipeline::new(vec![shell("exec sleep 10", Duration::from_millis(100))]);
+        assert!(matches!(
+            pipeline.ready().await,
+            Err(StageFailure::TimedOut { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stderr_lines() {
+        let (sender, mut lines) = tokio::sync::mpsc::unbounded_channel();
+        let mut stage = shell(
+            "printf '10%%\\r20%%\\rdone\\n' >&2; printf hello",
+            Duration::from_secs(5),
+        );
+        stage.stderr_lines = Some(sender);
+        let mut pipeline = ProcessPipeline::new(vec![stage]);
+        let mut output = vec![];
+        pipeline.read_to_end(&mut output).await.unwrap();
+        let mut received = vec![];
+        while let Some(line) = lines.recv().await {
+            received.push(line);
+        }
+        assert_eq!(vec!["10%", "20%", "done"], received);
+    }
+}
@@ -0,0 +1,165 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use regex::Regex;
+use tokio::sync::{mpsc::UnboundedReceiver, watch};
+
+/// Steps of creating an fx, in the order they are taken.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Progress {
+    /// looking up the source
+    Resolving,
+    /// waiting for the turn to render, with the number of renders ahead
+    Queued(usize),
+    /// the percentage when the downloader tells it
+    Downloading(Option<f32>),
+    Cutting,
+    /// posting the preview
+    Uploading,
+}
+
+impl Progress {
+    fn step(&self) -> u8 {
+        match self {
+            Progress::Resolving => 0,
+            Progress::Queued(_) => 1,
+            Progress::Downloading(_) => 2,
+            Progress::Cutting => 3,
+            Progress::Uploading => 4,
+        }
+    }
+}
+
+/// Reports the progress of creating an fx to whoever watches it, the latest report replaces
+/// earlier ones. Reports of steps already passed are dropped. ffmpeg starts cutting on the first
+/// bytes downloaded, so the cut is only told once the downloaders watched exit, and their
+/// percentages show until then.
+#[derive(Clone)]
+pub struct ProgressReporter {
+    sender: Arc<watch::Sender<Progress>>,
+    /// downloaders still running
+    downloading: Arc<AtomicUsize>,
+}
+
+impl ProgressReporter {
+    pub fn new() -> (Self, watch::Receiver<Progress>) {
+        let (sender, receiver) = watch::channel(Progress::Resolving);
+        let reporter = Self {
+            sender: Arc::new(sender),
+            downloading: Arc::default(),
+        };
+        (reporter, receiver)
+    }
+
+    pub fn report(&self, progress: Progress) {
+        if progress == Progress::Cutting && self.downloading.load(Ordering::SeqCst) > 0 {
+            return;
+        }
+        self.send(progress);
+    }
+
+    fn send(&self, progress: Progress) {
+        if progress.step() < self.sender.borrow().step() {
+            return;
+        }
+        // nobody may be watching
+        let _result = self.sender.send(progress);
+    }
+
+    /// Report the percentage the downloader prints among `lines` of its output, and the cut once
+    /// the output ends as the downloader exits.
+    pub(crate) fn watch_downloader(&self, mut lines: UnboundedReceiver<String>) {
+        let reporter = self.clone();
+        let pattern = download_pattern();
+        self.downloading.fetch_add(1, Ordering::SeqCst);
+        tokio::spawn(async move {
+            while let Some(line) = lines.recv().await {
+                if let Some(percentage) = download_percentage(&pattern, &line) {
+                    reporter.report(Progress::Downloading(Some(percentage)));
+                }
+            }
+            if reporter.downloading.fetch_sub(1, Ordering::SeqCst) == 1 {
+                reporter.send(Progress::Cutting);
+            }
+        });
+    }
+}
+
+/// Reports nobody watches.
+impl Default for ProgressReporter {
+    fn default() -> Self {
+        Self::new().0
+    }
+}
+
+/// Progress lines of youtube-dl and yt-dlp, e.g.
+/// `[download]  42.1% of 3.20MiB at 1.05MiB/s ETA 00:02`.
+fn download_pattern() -> Regex {
+    Regex::new(r"^\[download\]\s+(\d+(?:\.\d+)?)%").unwrap()
+}
+
+fn download_percentage(pattern: &Regex, line: &str) -> Option<f32> {
+    pattern
+        .captures(line.trim_start())
+        .and_then(|captures| captures[1].parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use test_case::test_case;
+    use tokio::sync::{mpsc, watch};
+
+    use super::{download_pattern, download_percentage, Progress, ProgressReporter};
+
+    #[test_case("[download]  42.1% of 3.20MiB at 1.05MiB/s ETA 00:02" => Some(42.1); "youtube-dl")]
+    #[test_case("[download] 100% of ~3.20MiB in 00:03" => Some(100.0); "finished")]
+    #[test_case("[youtube] abc: Downloading webpage" => None; "other line")]
+    #[test_case("[download] Destination: -" => None; "destination")]
+    fn test_download_percentage(line: &str) -> Option<f32> {
+        download_percentage(&download_pattern(), line)
+    }
+
+    #[test]
+    fn test_steps_only_move_forward() {
+        let (reporter, progress) = ProgressReporter::new();
+        reporter.report(Progress::Downloading(Some(10.0)));
+        reporter.report(Progress::Cutting);
+        reporter.report(Progress::Downloading(Some(90.0)));
+        assert_eq!(Progress::Cutting, *progress.borrow());
+    }
+
+    async fn next(progress: &mut watch::Receiver<Progress>) -> Progress {
+        tokio::time::timeout(Duration::from_secs(1), progress.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        let next = *progress.borrow();
+        next
+    }
+
+    #[tokio::test]
+    async fn test_percentages_follow_the_first_output() {
+        let (reporter, mut progress) = ProgressReporter::new();
+        let (lines, output) = mpsc::unbounded_channel();
+        reporter.watch_downloader(output);
+        // told as the first output of ffmpeg comes, while the download goes on
+        reporter.report(Progress::Cutting);
+        lines
+            .send("[download]  42.0% of 3.20MiB".to_string())
+            .unwrap();
+        assert_eq!(Progress::Downloading(Some(42.0)), next(&mut progress).await);
+        lines
+            .send("[download] 100% of 3.20MiB".to_string())
+            .unwrap();
+        assert_eq!(
+            Progress::Downloading(Some(100.0)),
+            next(&mut progress).await
+        );
+        drop(lines);
+        assert_eq!(Progress::Cutting, next(&mut progress).await);
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display};
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use serenity::model::id::{GuildId, UserId};
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::sync::Notify;
+
+use crate::config;
+
+use super::progress::{Progress, ProgressReporter};
+
+/// Who a render is for, the limits of the queue are counted by them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JobOwner {
+    pub guild: Option<GuildId>,
+    pub user: Option<UserId>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum QueueError {
+    /// the user has as many renders waiting or running as they may
+    TooManyPending,
+}
+
+impl Display for QueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueueError::TooManyPending => {
+                write!(f, "too many fx of yours are being made, try again later")
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    next_ticket: u64,
+    waiting: VecDeque<(u64, JobOwner)>,
+    running: usize,
+    running_by_guild: HashMap<GuildId, usize>,
+    pending_by_user: HashMap<UserId, usize>,
+}
+
+impl QueueState {
+    /// Start the job of `ticket` if it may run now, otherwise the number of jobs ahead of it.
+    /// Jobs of guilds at their limit don't hold back the jobs of other guilds.
+    fn try_start(&mut self, ticket: u64, limits: &config::Queue) -> Result<(), usize> {
+        let index = self
+            .waiting
+            .iter()
+            .position(|(waiting, _)| *waiting == ticket)
+            .expect("waiting ticket");
+        if self.running >= limits.concurrency {
+            return Err(index);
+        }
+        let may_run = |owner: &JobOwner| match owner.guild {
+            Some(guild) => {
+                self.running_by_guild.get(&guild).copied().unwrap_or(0) < limits.guild_concurrency
+            }
+            None => true,
+        };
+        if self
+            .waiting
+            .iter()
+            .take(index)
+            .any(|(_, owner)| may_run(owner))
+        {
+            return Err(index);
+        }
+        let owner = self.waiting[index].1;
+        if !may_run(&owner) {
+            return Err(index);
+        }
+        self.waiting.remove(index);
+        self.running += 1;
+        if let Some(guild) = owner.guild {
+            *self.running_by_guild.entry(guild).or_default() += 1;
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self, owner: &JobOwner) {
+        if let Some(user) = owner.user {
+            if let Some(pending) = self.pending_by_user.get_mut(&user) {
+                *pending -= 1;
+                if *pending == 0 {
+                    self.pending_by_user.remove(&user);
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self, owner: &JobOwner) {
+        self.running -= 1;
+        if let Some(guild) = owner.guild {
+            if let Some(running) = self.running_by_guild.get_mut(&guild) {
+                *running -= 1;
+                if *running == 0 {
+                    self.running_by_guild.remove(&guild);
+                }
+            }
+        }
+        self.leave(owner);
+    }
+}
+
+/// Queue of renders, which run youtube-dl and ffmpeg, so only so many of them run at once in
+/// total and for each guild. Jobs start in the order they joined.
+pub struct RenderQueue {
+    limits: config::Queue,
+    state: Mutex<QueueState>,
+    changed: Notify,
+}
+
+impl RenderQueue {
+    pub fn new(limits: config::Queue) -> Self {
+        Self {
+            limits,
+            state: Mutex::new(QueueState::default()),
+            changed: Notify::new(),
+        }
+    }
+
+    /// Wait for the turn of a job of `owner`, telling `progress` its position meanwhile. The job
+    /// runs until the permit is dropped.
+    pub async fn join(
+        self: &Arc<Self>,
+        owner: JobOwner,
+        progress: &ProgressReporter,
+    ) -> Result<JobPermit, QueueError> {
+        let mut waiting = {
+            let mut state = self.state.lock().unwrap();
+            if let Some(user) = owner.user {
+                let pending = state.pending_by_user.entry(user).or_default();
+                if *pending >= self.limits.user_pending {
+                    return Err(QueueError::TooManyPending);
+                }
+                *pending += 1;
+            }
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+            state.waiting.push_back((ticket, owner));
+            Waiting {
+                queue: self.clone(),
+                ticket,
+                owner,
+                started: false,
+            }
+        };
+        let mut reported = None;
+        loop {
+            // registered before looking, so a change in between still wakes us
+            let changed = self.changed.notified();
+            let position = match self
+                .state
+                .lock()
+                .unwrap()
+                .try_start(waiting.ticket, &self.limits)
+            {
+                Ok(()) => {
+                    waiting.started = true;
+                    return Ok(JobPermit {
+                        queue: self.clone(),
+                        owner,
+                    });
+                }
+                Err(position) => position,
+            };
+            if reported != Some(position) {
+                progress.report(Progress::Queued(position));
+                reported = Some(position);
+            }
+            changed.await;
+        }
+    }
+}
+
+/// A job waiting for its turn, leaves the queue if it's given up before it starts.
+struct Waiting {
+    queue: Arc<RenderQueue>,
+    ticket: u64,
+    owner: JobOwner,
+    started: bool,
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        if self.started {
+            return;
+        }
+        let mut state = self.queue.state.lock().unwrap();
+        state.waiting.retain(|(ticket, _)| *ticket != self.ticket);
+        state.leave(&self.owner);
+        drop(state);
+        self.queue.changed.notify_waiters();
+    }
+}
+
+/// A running job, the next ones may start once it's dropped.
+pub struct JobPermit {
+    queue: Arc<RenderQueue>,
+    owner: JobOwner,
+}
+
+impl Drop for JobPermit {
+    fn drop(&mut self) {
+        self.queue.state.lock().unwrap().finish(&self.owner);
+        self.queue.changed.notify_waiters();
+    }
+}
+
+/// Media streamed while it's being rendered, the job of the render runs until the media is read
+/// to its end or dropped.
+pub struct QueuedMedia<M> {
+    media: M,
+    job: Option<JobPermit>,
+}
+
+impl<M> QueuedMedia<M> {
+    pub fn new(media: M, job: Option<JobPermit>) -> Self {
+        Self { media, job }
+    }
+}
+
+impl<M: AsyncRead + Unpin> AsyncRead for QueuedMedia<M> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        let polled = Pin::new(&mut this.media).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = polled {
+            if buf.filled().len() == filled && buf.remaining() > 0 {
+                this.job = None;
+            }
+        }
+        polled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::FutureExt;
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    fn queue(
+        concurrency: usize,
+        guild_concurrency: usize,
+        user_pending: usize,
+    ) -> Arc<RenderQueue> {
+        Arc::new(RenderQueue::new(config::Queue {
+            concurrency,
+            guild_concurrency,
+            user_pending,
+        }))
+    }
+
+    fn owner(guild: u64, user: u64) -> JobOwner {
+        JobOwner {
+            guild: Some(GuildId(guild)),
+            user: Some(UserId(user)),
+        }
+    }
+
+    async fn join(queue: &Arc<RenderQueue>, owner: JobOwner) -> Result<JobPermit, QueueError> {
+        queue.join(owner, &ProgressReporter::default()).await
+    }
+
+    #[tokio::test]
+    async fn test_global_limit() {
+        let queue = queue(2, 2, 3);
+        let first = join(&queue, owner(1, 1)).await.unwrap();
+        let _second = join(&queue, owner(2, 2)).await.unwrap();
+        let third = join(&queue, owner(3, 3));
+        tokio::pin!(third);
+        assert!((&mut third).now_or_never().is_none());
+        drop(first);
+        assert!(tokio::time::timeout(Duration::from_secs(1), third)
+            .await
+            .unwrap()
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_busy_guild_does_not_hold_back_others() {
+        let queue = queue(3, 1, 3);
+        let _first = join(&queue, owner(1, 1)).await.unwrap();
+        let second = join(&queue, owner(1, 2));
+        tokio::pin!(second);
+        assert!((&mut second).now_or_never().is_none());
+        assert!(join(&queue, owner(2, 3)).now_or_never().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_user_pending_limit() {
+        let queue = queue(1, 1, 2);
+        let _first = join(&queue, owner(1, 1)).await.unwrap();
+        let second = queue.clone();
+        let waiting = tokio::spawn(async move { join(&second, owner(1, 1)).await.map(|_| ()) });
+        tokio::task::yield_now().await;
+        assert_eq!(
+            Some(QueueError::TooManyPending),
+            join(&queue, owner(2, 1)).await.err()
+        );
+        waiting.abort();
+        let _ = waiting.await;
+        // the aborted job left the queue
+        let third = join(&queue, owner(2, 1));
+        tokio::pin!(third);
+        assert!((&mut third).now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_position_is_reported() {
+        let queue = queue(1, 1, 3);
+        let first = join(&queue, owner(1, 1)).await.unwrap();
+        let _second = {
+            let queue = queue.clone();
+            tokio::spawn(async move { join(&queue, owner(2, 2)).await.map(|_| ()) })
+        };
+        tokio::task::yield_now().await;
+        let (reporter, progress) = ProgressReporter::new();
+        let third = {
+            let queue = queue.clone();
+            tokio::spawn(async move { queue.join(owner(3, 3), &reporter).await.is_ok() })
+        };
+        tokio::task::yield_now().await;
+        assert_eq!(Progress::Queued(1), *progress.borrow());
+        drop(first);
+        assert!(third.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_job_of_media_ends_once_read() {
+        let queue = queue(1, 1, 3);
+        let job = join(&queue, owner(1, 1)).await.unwrap();
+        let mut media = QueuedMedia::new(&b"media"[..], Some(job));
+        let mut buf = [0; 5];
+        media.read_exact(&mut buf).await.unwrap();
+        assert!(join(&queue, owner(2, 2)).now_or_never().is_none());
+        assert_eq!(0, media.read(&mut buf).await.unwrap());
+        assert!(join(&queue, owner(2, 2)).now_or_never().is_some());
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource};
+use once_cell::sync::Lazy;
+use unic_langid::{langid, LanguageIdentifier};
+
+/// Languages the bot speaks, each with a catalog under `locales/`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Locale {
+    ZhTw,
+    EnUs,
+}
+
+/// The bot spoke only Chinese before it had catalogs.
+impl Default for Locale {
+    fn default() -> Self {
+        Locale::ZhTw
+    }
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::ZhTw, Locale::EnUs];
+
+    /// The locale code on Discord.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Locale::ZhTw => "zh-TW",
+            Locale::EnUs => "en-US",
+        }
+    }
+
+    fn language(&self) -> LanguageIdentifier {
+        match self {
+            Locale::ZhTw => langid!("zh-TW"),
+            Locale::EnUs => langid!("en-US"),
+        }
+    }
+
+    fn catalog(&self) -> &'static str {
+        match self {
+            Locale::ZhTw => include_str!("../locales/zh-TW/bot.ftl"),
+            Locale::EnUs => include_str!("../locales/en-US/bot.ftl"),
+        }
+    }
+
+    /// The locale for the Discord locale `code`, every English locale gets American English.
+    pub fn from_code(code: &str) -> Option<Locale> {
+        match code {
+            "zh-TW" => Some(Locale::ZhTw),
+            code if code.starts_with("en-") => Some(Locale::EnUs),
+            _ => None,
+        }
+    }
+
+    /// The locale of the user if the bot speaks it, otherwise the preferred locale of the guild.
+    pub fn choose(user: Option<&str>, guild: Option<&str>) -> Locale {
+        user.and_then(Self::from_code)
+            .or_else(|| guild.and_then(Self::from_code))
+            .unwrap_or_default()
+    }
+
+    /// The text of the message `id`, or of its attribute when written as `message.attribute`.
+    pub fn text(&self, id: &str) -> String {
+        self.text_with(id, &[])
+    }
+
+    pub fn text_with(&self, id: &str, args: &[(&str, &dyn Display)]) -> String {
+        CATALOG.format(*self, id, args)
+    }
+}
+
+static CATALOG: Lazy<Catalog> = Lazy::new(Catalog::load);
+
+struct Catalog {
+    bundles: HashMap<Locale, FluentBundle<FluentResource>>,
+}
+
+impl Catalog {
+    fn load() -> Self {
+        let bundles = Locale::ALL
+            .iter()
+            .map(|locale| {
+                let resource = FluentResource::try_new(locale.catalog().to_string())
+                    .unwrap_or_else(|(_, errors)| {
+                        panic!("malformed catalog of {}, {:?}", locale.code(), errors)
+                    });
+                let mut bundle = FluentBundle::new_concurrent(vec![locale.language()]);
+                // the marks isolating arguments show up as stray characters on Discord
+                bundle.set_use_isolating(false);
+                bundle.add_resource(resource).unwrap_or_else(|errors| {
+                    panic!("duplicated messages for {}, {:?}", locale.code(), errors)
+                });
+                (*locale, bundle)
+            })
+            .collect();
+        Self { bundles }
+    }
+
+    /// Messages missing in `locale` are taken from the default one.
+    fn format(&self, locale: Locale, id: &str, args: &[(&str, &dyn Display)]) -> String {
+        if let Some(text) = self.try_format(locale, id, args) {
+            return text;
+        }
+        log::warn!("message {} is missing for {}", id, locale.code());
+        self.try_format(Locale::default(), id, args)
+            .unwrap_or_else(|| id.to_string())
+    }
+
+    fn try_format(
+        &self,
+        locale: Locale,
+        id: &str,
+        args: &[(&str, &dyn Display)],
+    ) -> Option<String> {
+        let bundle = &self.bundles[&locale];
+        let (message, attribute) = match id.split_once('.') {
+            Some((message, attribute)) => (message, Some(attribute)),
+            None => (id, None),
+        };
+        let message = bundle.get_message(message)?;
+        let pattern = match attribute {
+            Some(attribute) => message.get_attribute(attribute)?.value(),
+            None => message.value()?,
+        };
+        let mut fluent_args = FluentArgs::new();
+        for (name, value) in args {
+            fluent_args.set(*name, value.to_string());
+        }
+        let mut errors = vec![];
+        let text = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+        if !errors.is_empty() {
+            log::warn!("fail to format message {}, {:?}", id, errors);
+        }
+        Some(text.into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use fluent_bundle::FluentResource;
+    use fluent_syntax::ast::Entry;
+    use test_case::test_case;
+
+    use super::Locale;
+
+    fn message_ids(locale: Locale) -> HashSet<String> {
+        let resource = FluentResource::try_new(locale.catalog().to_string()).unwrap();
+        resource
+            .entries()
+            .filter_map(|entry| match entry {
+                Entry::Message(message) => Some(message.id.name.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_catalogs_have_the_same_messages() {
+        let default = message_ids(Locale::default());
+        for locale in Locale::ALL {
+            assert_eq!(default, message_ids(locale), "{}", locale.code());
+        }
+    }
+
+    #[test_case(Some("en-GB"), Some("zh-TW") => Locale::EnUs; "user")]
+    #[test_case(Some("ja"), Some("en-US") => Locale::EnUs; "guild")]
+    #[test_case(Some("ja"), None => Locale::ZhTw; "default")]
+    fn test_choose(user: Option<&str>, guild: Option<&str>) -> Locale {
+        Locale::choose(user, guild)
+    }
+
+    #[test]
+    fn test_text_with_args() {
+        let text = Locale::EnUs.text_with("progress-queued", &[("ahead", &2)]);
+        assert_eq!("Meow! Waiting in line, 2 fx ahead of yours", text);
+        assert_eq!(
+            "音效指令的名稱",
+            Locale::ZhTw.text("option-name.description")
+        );
+    }
+}
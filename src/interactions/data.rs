@@ -2,7 +2,7 @@ use crate::fx::Fx;
 use mongodb::{
     bson::{doc, oid::ObjectId},
     error::Result as MongoDBResult,
-    results::InsertOneResult,
+    results::{InsertOneResult, UpdateResult},
 };
 use serde::{Deserialize, Serialize};
 const INTERACTION_DATA_COLLECTION: &str = "interaction_data";
@@ -38,8 +38,15 @@ impl InteractionDataRegistry {
     pub async fn get(&self, id: ObjectId) -> MongoDBResult<Option<InteractionData>> {
         self.database
             .collection::<WithID<InteractionData>>(INTERACTION_DATA_COLLECTION)
-            .find_one(doc! {"id": id}, None)
+            .find_one(doc! {"_id": id}, None)
             .await
             .map(|option| option.map(|WithID { data, .. }| data))
     }
+
+    pub async fn update(&self, id: ObjectId, data: InteractionData) -> MongoDBResult<UpdateResult> {
+        self.database
+            .collection(INTERACTION_DATA_COLLECTION)
+            .replace_one(doc! {"_id": id}, data, None)
+            .await
+    }
 }
@@ -1,14 +1,21 @@
 use crate::{
-    audio::{mp3_to_songbird_input, try_join_authors_channel, try_play_source},
-    discord::InteractionWrapper,
+    audio::{media_stream_to_songbird_input, try_join_authors_channel, try_play_source},
+    discord::{InteractionWrapper, Localized},
+    error::{Reason, UserError, UserFacing},
     fx::{
-        Controller, Creator, DiscordOrigin, Fx, FxIdentity, GetFxError, MediaOrigin, PreviewingFx,
-        Repository, RepositoryGetError,
+        effect::Effect,
+        progress::{Progress, ProgressReporter},
+        Controller, Creator, DiscordOrigin, Fx, FxIdentity, FxWithMedia, MediaOrigin, Nudge,
+        Playable, PreviewOptions, PreviewingFx, Repository, Source, Speech, Upload, UploadError,
     },
+    i18n::Locale,
 };
+use mongodb::bson::oid::ObjectId;
 use rand::{distributions::Uniform, prelude::Distribution};
 use serenity::{
-    builder::{CreateApplicationCommand, CreateEmbed},
+    builder::{
+        CreateApplicationCommand, CreateApplicationCommandOption, CreateComponents, CreateEmbed,
+    },
     client::Context,
     model::{
         application::{
@@ -21,11 +28,13 @@ use serenity::{
                 InteractionResponseType,
             },
         },
-        channel::{AttachmentType, Message},
+        channel::{Attachment, AttachmentType, Message},
+        id::MessageId,
     },
     utils::Colour,
 };
-use std::{borrow::Cow, io::Cursor, time::Duration};
+use std::{borrow::Cow, time::Duration};
+use tokio::sync::watch;
 
 use super::data::{InteractionData, InteractionDataRegistry};
 
@@ -44,6 +53,204 @@ fn check_message<R>(result: serenity::Result<R>) {
     }
 }
 
+/// Name and description of the command in every locale, `id` is its message in the catalogs.
+fn localize_command<'c>(
+    command: &'c mut CreateApplicationCommand,
+    id: &str,
+) -> &'c mut CreateApplicationCommand {
+    let description = format!("{}.description", id);
+    let default = Locale::default();
+    command
+        .name(default.text(id))
+        .description(default.text(&description));
+    for locale in Locale::ALL.into_iter().filter(|locale| *locale != default) {
+        command
+            .name_localized(locale.code(), locale.text(id))
+            .description_localized(locale.code(), locale.text(&description));
+    }
+    command
+}
+
+/// Name and description of the option in every locale, `id` is its message in the catalogs.
+/// Interactions carry the name in the default locale, which options are looked up by.
+fn localize_option<'o>(
+    option: &'o mut CreateApplicationCommandOption,
+    id: &str,
+) -> &'o mut CreateApplicationCommandOption {
+    let description = format!("{}.description", id);
+    let default = Locale::default();
+    option
+        .name(default.text(id))
+        .description(default.text(&description));
+    for locale in Locale::ALL.into_iter().filter(|locale| *locale != default) {
+        option
+            .name_localized(locale.code(), locale.text(id))
+            .description_localized(locale.code(), locale.text(&description));
+    }
+    option
+}
+
+/// Buttons adjusting the cut on a preview, by the action in their custom id.
+const NUDGES: [(&str, Nudge); 6] = [
+    ("start-1", Nudge::Start(-1000)),
+    ("start-0.1", Nudge::Start(-100)),
+    ("start+0.1", Nudge::Start(100)),
+    ("start+1", Nudge::Start(1000)),
+    ("length-0.5", Nudge::Length(-500)),
+    ("length+0.5", Nudge::Length(500)),
+];
+
+fn nudge_label(nudge: Nudge, locale: Locale) -> String {
+    let (id, millis) = match nudge {
+        Nudge::Start(millis) => ("nudge-start", millis),
+        Nudge::Length(millis) => ("nudge-length", millis),
+    };
+    let seconds = format!("{:+}", millis as f64 / 1000.0);
+    locale.text_with(id, &[("seconds", &seconds)])
+}
+
+/// Button action on a preview playing the draft in the voice channel of the clicker.
+pub(super) const LISTEN_ACTION: &str = "listen";
+
+/// The nudge of a button action on a preview.
+pub(super) fn nudge(action: &str) -> Option<Nudge> {
+    NUDGES
+        .iter()
+        .find(|(name, _)| *name == action)
+        .map(|(_, nudge)| *nudge)
+}
+
+fn seconds(duration: Duration, locale: Locale) -> String {
+    let seconds = format!("{:.1}", duration.as_secs_f64());
+    locale.text_with("seconds", &[("seconds", &seconds)])
+}
+
+/// Details of an fx, shared by previews and `/fx info`.
+fn fx_embed(fx: &Fx, locale: Locale) -> CreateEmbed {
+    let mut embed = CreateEmbed::default();
+    embed
+        .colour(Colour::ORANGE)
+        .title(&fx.name)
+        .description(&fx.description)
+        .field(locale.text("embed-source"), &fx.media.source, false);
+    if let Some(metadata) = &fx.metadata {
+        if let Some(title) = &metadata.title {
+            let title = match &metadata.uploader {
+                Some(uploader) => format!("{} — {}", title, uploader),
+                None => title.clone(),
+            };
+            embed.field(locale.text("embed-title"), title, false);
+        }
+        if let Some(duration) = metadata.duration {
+            embed.field(
+                locale.text("embed-source-length"),
+                seconds(duration, locale),
+                false,
+            );
+        }
+        if let Some(thumbnail) = &metadata.thumbnail {
+            embed.thumbnail(thumbnail);
+        }
+    }
+    embed
+        .field(
+            locale.text("embed-start"),
+            seconds(fx.media.start, locale),
+            false,
+        )
+        .field(
+            locale.text("embed-length"),
+            seconds(fx.media.length, locale),
+            false,
+        );
+    if !fx.media.effects.is_empty() {
+        let effects: Vec<String> = fx.media.effects.iter().map(Effect::to_string).collect();
+        embed.field(locale.text("embed-effects"), effects.join(", "), false);
+    }
+    embed
+}
+
+pub(super) fn preview_embed(preview: &PreviewingFx, locale: Locale) -> CreateEmbed {
+    let mut embed = fx_embed(&preview.fx, locale);
+    if let Some(trimmed) = preview.trimmed {
+        let leading = seconds(trimmed.leading, locale);
+        let trailing = seconds(trimmed.trailing, locale);
+        embed.field(
+            locale.text("embed-trimmed"),
+            locale.text_with(
+                "embed-trimmed-value",
+                &[("leading", &leading), ("trailing", &trailing)],
+            ),
+            false,
+        );
+    }
+    if preview.waveform.is_some() {
+        embed.attachment(WAVEFORM_FILENAME);
+    }
+    embed
+}
+
+/// The rendered audio of a preview, and its waveform if it was drawn.
+pub(super) fn preview_files(preview: &PreviewingFx) -> Vec<AttachmentType<'_>> {
+    let mut files = vec![AttachmentType::Bytes {
+        data: Cow::Borrowed(preview.media.as_slice()),
+        filename: format!("preview_{}.mp3", preview.fx.name),
+    }];
+    if let Some(waveform) = &preview.waveform {
+        files.push(AttachmentType::Bytes {
+            data: Cow::Borrowed(waveform.as_slice()),
+            filename: WAVEFORM_FILENAME.to_string(),
+        });
+    }
+    files
+}
+
+/// Confirmation buttons of a preview, and the nudges if the cut can be moved.
+pub(super) fn preview_components(
+    id: ObjectId,
+    media: &MediaOrigin,
+    locale: Locale,
+) -> CreateComponents {
+    let mut components = CreateComponents::default();
+    components.create_action_row(|row| {
+        row.create_button(|button| {
+            button
+                .style(ButtonStyle::Primary)
+                .label(locale.text("button-create"))
+                .custom_id(format!("{}:create", id.to_hex()))
+        })
+        .create_button(|button| {
+            button
+                .style(ButtonStyle::Secondary)
+                .label(locale.text("button-listen"))
+                .custom_id(format!("{}:{}", id.to_hex(), LISTEN_ACTION))
+        })
+        .create_button(|button| {
+            button
+                .style(ButtonStyle::Secondary)
+                .label(locale.text("button-cancel"))
+                .custom_id(format!("{}:cancel", id.to_hex()))
+        })
+    });
+    if media.can_cut() {
+        // a row holds at most five buttons
+        for nudges in [&NUDGES[..4], &NUDGES[4..]] {
+            components.create_action_row(|row| {
+                for (action, nudge) in nudges {
+                    row.create_button(|button| {
+                        button
+                            .style(ButtonStyle::Secondary)
+                            .label(nudge_label(*nudge, locale))
+                            .custom_id(format!("{}:{}", id.to_hex(), action))
+                    });
+                }
+                row
+            });
+        }
+    }
+    components
+}
+
 impl<'a, C, R> CreateFxCommand<'a, C, R>
 where
     C: Creator,
@@ -53,60 +260,134 @@ where
         &self,
         command: &'c mut CreateApplicationCommand,
     ) -> &'c mut CreateApplicationCommand {
-        command
-            .name("fx")
-            .description("音效指令")
+        localize_command(command, "command-fx")
             .create_option(|option| {
-                option
-                    .name("create")
-                    .description("創立音效指令")
+                localize_option(option, "subcommand-create")
                     .kind(CommandOptionType::SubCommand)
                     .create_sub_option(|option| {
-                        option
-                            .name("名稱")
-                            .description("音效指令的名稱")
+                        localize_option(option, "option-name")
                             .kind(CommandOptionType::String)
                             .required(true)
                     })
                     .create_sub_option(|option| {
-                        option
-                            .name("描述")
-                            .description("音效指令的描述")
+                        localize_option(option, "option-description")
                             .kind(CommandOptionType::String)
                             .required(true)
                     })
                     .create_sub_option(|option| {
-                        option
-                            .name("來源")
-                            .description("填入影片的URL")
-                            .kind(CommandOptionType::String)
-                            .required(true)
+                        localize_option(option, "option-source").kind(CommandOptionType::String)
+                    })
+                    .create_sub_option(|option| {
+                        localize_option(option, "option-attachment")
+                            .kind(CommandOptionType::Attachment)
                     })
                     .create_sub_option(|option| {
-                        option
-                            .name("開始秒數")
-                            .description("開始秒數，預設0秒開始")
+                        localize_option(option, "option-start")
                             .kind(CommandOptionType::Integer)
                             .min_int_value(0)
                     })
                     .create_sub_option(|option| {
-                        option
-                            .name("持續秒數")
-                            .description("持續秒數，最大20秒，預設5秒")
+                        localize_option(option, "option-duration")
                             .kind(CommandOptionType::Integer)
                             .max_int_value(20)
                             .min_int_value(1)
                     })
+                    .create_sub_option(|option| {
+                        localize_option(option, "option-speed")
+                            .kind(CommandOptionType::Number)
+                            .min_number_value(0.5)
+                            .max_number_value(2.0)
+                    })
+                    .create_sub_option(|option| {
+                        localize_option(option, "option-pitch")
+                            .kind(CommandOptionType::Integer)
+                            .min_int_value(-12)
+                            .max_int_value(12)
+                    })
+                    .create_sub_option(|option| {
+                        localize_option(option, "option-reverse").kind(CommandOptionType::Boolean)
+                    })
+                    .create_sub_option(|option| {
+                        localize_option(option, "option-echo")
+                            .kind(CommandOptionType::Integer)
+                            .min_int_value(1)
+                            .max_int_value(2000)
+                    })
+                    .create_sub_option(|option| {
+                        localize_option(option, "option-bass")
+                            .kind(CommandOptionType::Integer)
+                            .min_int_value(-20)
+                            .max_int_value(20)
+                    })
+                    .create_sub_option(|option| {
+                        localize_option(option, "option-fade-in")
+                            .kind(CommandOptionType::Number)
+                            .min_number_value(0.0)
+                            .max_number_value(20.0)
+                    })
+                    .create_sub_option(|option| {
+                        localize_option(option, "option-fade-out")
+                            .kind(CommandOptionType::Number)
+                            .min_number_value(0.0)
+                            .max_number_value(20.0)
+                    })
+                    .create_sub_option(|option| {
+                        localize_option(option, "option-context").kind(CommandOptionType::Boolean)
+                    })
+                    .create_sub_option(|option| {
+                        localize_option(option, "option-trim").kind(CommandOptionType::Boolean)
+                    })
+            })
+            .create_option(|option| {
+                localize_option(option, "subcommand-tts")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        localize_option(option, "option-name")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                    })
+                    .create_sub_option(|option| {
+                        localize_option(option, "option-text")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                    })
+                    .create_sub_option(|option| {
+                        localize_option(option, "option-voice").kind(CommandOptionType::String)
+                    })
+            })
+            .create_option(|option| {
+                localize_option(option, "subcommand-combo")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        localize_option(option, "option-name")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                    })
+                    .create_sub_option(|option| {
+                        localize_option(option, "option-description")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                    })
+                    .create_sub_option(|option| {
+                        localize_option(option, "option-segments")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                    })
+            })
+            .create_option(|option| {
+                localize_option(option, "subcommand-info")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        localize_option(option, "option-name")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                    })
             })
             .create_option(|option| {
-                option
-                    .name("play")
-                    .description("播放音效指令")
+                localize_option(option, "subcommand-play")
                     .kind(CommandOptionType::SubCommand)
                     .create_sub_option(|option| {
-                        option
-                            .name("名稱")
-                            .description("音效指令的名稱")
+                        localize_option(option, "option-name")
                             .kind(CommandOptionType::String)
                             .required(true)
                     })
@@ -120,6 +401,7 @@ where
                 })
                 .await,
         );
+        let locale = InteractionWrapper(ctx, command).locale();
         let discord_origin: DiscordOrigin = command.clone().into();
         let subcommand = match command
             .data
@@ -134,24 +416,98 @@ where
         };
         match subcommand {
             "create" => {
-                if let Some(fx) = Self::option_fx(
-                    discord_origin,
-                    &command.data.options.get(0).unwrap().options,
-                ) {
-                    check_message(Self::post_processing(ctx, command).await);
-                    match self.controller.init_create_fx(fx).await {
-                        Ok(preview) => match self.post_preview(ctx, command, preview).await {
-                            Ok(_) => (),
-                            Err(why) => {
-                                log::error!("{:?}", why);
-                            }
-                        },
-                        Err(why) => {
-                            log::error!("{:?}", why);
+                let options = &command.data.options.get(0).unwrap().options;
+                if let Some(fx) = Self::option_fx(discord_origin, options) {
+                    let processing = Processing::post(ctx, command, locale).await;
+                    if let Source::Attachment { attachment: upload } = &fx.media.source {
+                        processing.show(Progress::Downloading(None)).await;
+                        let attachment = Self::option_attachment(options).unwrap();
+                        if let Err(why) = self.upload(attachment, upload).await {
+                            processing.fail(&why.into()).await;
+                            return;
                         }
+                    } else {
+                        processing.show(Progress::Resolving).await;
                     }
+                    let fx = match self.controller.describe(fx).await {
+                        Ok(fx) => fx,
+                        Err(why) => {
+                            processing.fail(&why.into()).await;
+                            return;
+                        }
+                    };
+                    let options = PreviewOptions {
+                        context: Self::option_flag(options, "option-context"),
+                        trim_silence: Self::option_flag(options, "option-trim"),
+                    };
+                    self.preview(&processing, fx, options).await;
+                } else {
+                    check_message(Self::post_invalid(ctx, command, locale).await);
+                }
+            }
+            "tts" => {
+                let options = &command.data.options.get(0).unwrap().options;
+                if let Some(fx) = Self::option_speech(discord_origin, options) {
+                    let processing = Processing::post(ctx, command, locale).await;
+                    self.preview(&processing, fx, PreviewOptions::default())
+                        .await;
                 } else {
-                    check_message(Self::post_invalid(ctx, command).await);
+                    check_message(Self::post_invalid(ctx, command, locale).await);
+                }
+            }
+            "combo" => {
+                let options = &command.data.options.get(0).unwrap().options;
+                let (name, description, spec) = match (
+                    Self::option_string(options, "option-name"),
+                    Self::option_string(options, "option-description"),
+                    Self::option_string(options, "option-segments"),
+                ) {
+                    (Some(name), Some(description), Some(spec)) => (name, description, spec),
+                    _ => {
+                        check_message(Self::post_invalid(ctx, command, locale).await);
+                        return;
+                    }
+                };
+                let processing = Processing::post(ctx, command, locale).await;
+                let guild_id = command.guild_id.unwrap();
+                let media = match self.controller.compose(guild_id, &spec).await {
+                    Ok(media) => media,
+                    Err(why) => {
+                        processing.fail(&why.into()).await;
+                        return;
+                    }
+                };
+                let fx = Fx {
+                    name,
+                    description,
+                    discord: discord_origin,
+                    media,
+                    metadata: None,
+                };
+                self.preview(&processing, fx, PreviewOptions::default())
+                    .await;
+            }
+            "info" => {
+                let options = &command.data.options.get(0).unwrap().options;
+                let name = match Self::option_string(options, "option-name") {
+                    Some(name) => name,
+                    None => {
+                        check_message(Self::post_invalid(ctx, command, locale).await);
+                        return;
+                    }
+                };
+                let identity = FxIdentity(command.guild_id.unwrap(), name);
+                match self.controller.repository().get(&identity).await {
+                    Ok(fx) => check_message(
+                        command
+                            .create_followup_message(ctx, |response| {
+                                response.add_embed(fx_embed(&fx, locale))
+                            })
+                            .await,
+                    ),
+                    Err(why) => {
+                        check_message(Self::post_failed(ctx, command, locale, why.into()).await)
+                    }
                 }
             }
             "play" => {
@@ -170,34 +526,38 @@ where
                 {
                     let guild_id = command.guild_id.unwrap();
                     let identity = FxIdentity(guild_id, name.clone());
-                    let fx_media = match self.controller.get(&identity).await {
+                    let (reporter, progress) = ProgressReporter::new();
+                    let playing = async {
+                        let played = self
+                            .controller
+                            .play(&identity, command.user.id, &reporter)
+                            .await;
+                        drop(reporter);
+                        played
+                    };
+                    // following ends once the reporter is dropped with the playing
+                    let (played, ()) =
+                        futures::join!(playing, follow_queue(ctx, command, locale, progress));
+                    let FxWithMedia(_fx, media) = match played {
                         Ok(fx) => fx,
-                        Err(GetFxError::Repository(RepositoryGetError::NotFound)) => {
-                            log::debug!("{:?} fx not found", &identity);
-                            if let Err(why) = command
-                                .create_followup_message(ctx, |response| {
-                                    response.content("本毛找不到此指令")
-                                })
-                                .await
-                            {
-                                log::error!("{:?}", why);
-                            }
-                            return;
-                        }
                         Err(why) => {
-                            log::error!("{:?}", why);
+                            check_message(
+                                Self::post_failed(ctx, command, locale, why.into()).await,
+                            );
                             return;
                         }
                     };
                     try_join_authors_channel(ctx, InteractionWrapper(ctx, command)).await;
-                    if let Err(err) = try_play_source(
-                        ctx,
-                        guild_id,
-                        mp3_to_songbird_input(Cursor::new(fx_media.1)),
-                    )
-                    .await
-                    {
-                        log::error!("{:?}", err);
+                    let input = match media {
+                        Playable::Decoded(media) => media.to_songbird_input(),
+                        Playable::Stream(media) => media_stream_to_songbird_input(media).await,
+                    };
+                    let played = match input {
+                        Ok(input) => try_play_source(ctx, guild_id, input).await,
+                        Err(why) => Err(why),
+                    };
+                    if let Err(why) = played {
+                        check_message(Self::post_failed(ctx, command, locale, why.into()).await);
                     }
                 }
             }
@@ -208,10 +568,181 @@ where
     }
 }
 
+const MAX_SPEECH_CHARS: usize = 200;
+const ECHO_DECAY_PERCENT: u32 = 40;
+/// Speech is cut at the same length as the longest fx.
+const MAX_SPEECH_LENGTH: Duration = Duration::from_secs(20);
+
+/// Uploads larger than this are rejected, it's the limit of attachments on Discord.
+const MAX_ATTACHMENT_SIZE: u64 = 25 * 1024 * 1024;
+/// Name of the waveform attached to a preview, the embed refers to it by name.
+const WAVEFORM_FILENAME: &str = "waveform.png";
+
 #[derive(Debug)]
 enum CreateFxError {
     Serenity(serenity::Error),
     Data(mongodb::error::Error),
+    Upload(UploadError),
+}
+
+impl UserFacing for CreateFxError {
+    fn reason(&self) -> Reason {
+        match self {
+            CreateFxError::Serenity(why) => why.reason(),
+            CreateFxError::Data(why) => why.reason(),
+            CreateFxError::Upload(why) => why.reason(),
+        }
+    }
+}
+
+/// Edits of the processing message are apart by this at least, to stay within rate limits.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+fn progress_text(progress: Progress, locale: Locale) -> String {
+    match progress {
+        Progress::Resolving => locale.text("progress-resolving"),
+        Progress::Queued(0) => locale.text("progress-queued-next"),
+        Progress::Queued(ahead) => locale.text_with("progress-queued", &[("ahead", &ahead)]),
+        Progress::Downloading(None) => locale.text("progress-downloading"),
+        Progress::Downloading(Some(percentage)) => {
+            let percentage = format!("{:.0}", percentage);
+            locale.text_with(
+                "progress-downloading-percentage",
+                &[("percentage", &percentage)],
+            )
+        }
+        Progress::Cutting => locale.text("progress-cutting"),
+        Progress::Uploading => locale.text("progress-uploading"),
+    }
+}
+
+/// The followup telling the user their fx is being made, kept up to date until the preview is
+/// posted. Failures are shown on it too.
+struct Processing<'c> {
+    ctx: &'c Context,
+    command: &'c ApplicationCommandInteraction,
+    locale: Locale,
+    /// missing if it couldn't be posted
+    message: Option<MessageId>,
+}
+
+impl<'c> Processing<'c> {
+    async fn post(
+        ctx: &'c Context,
+        command: &'c ApplicationCommandInteraction,
+        locale: Locale,
+    ) -> Processing<'c> {
+        let random_message = RandomMessage::new(&[
+            "processing-chicken",
+            "processing-plain",
+            "processing-treats",
+        ]);
+        let content = locale.text(random_message.next());
+        let message = match command
+            .create_followup_message(ctx, |message| message.content(content))
+            .await
+        {
+            Ok(message) => Some(message.id),
+            Err(why) => {
+                log::error!("fail to post the processing message, {:?}", why);
+                None
+            }
+        };
+        Self {
+            ctx,
+            command,
+            locale,
+            message,
+        }
+    }
+
+    async fn show(&self, progress: Progress) {
+        self.edit(progress_text(progress, self.locale)).await;
+    }
+
+    async fn edit(&self, content: String) {
+        if let Some(message) = self.message {
+            check_message(
+                self.command
+                    .edit_followup_message(self.ctx, message, |edit| edit.content(content))
+                    .await,
+            );
+        }
+    }
+
+    /// Show the progress until its reporter is dropped.
+    async fn follow(&self, mut progress: watch::Receiver<Progress>) {
+        while progress.changed().await.is_ok() {
+            let current = *progress.borrow();
+            self.show(current).await;
+            tokio::time::sleep(PROGRESS_INTERVAL).await;
+        }
+    }
+
+    async fn fail(&self, error: &UserError) {
+        let content = error.message(self.locale);
+        match self.message {
+            Some(_) => self.edit(content).await,
+            None => check_message(
+                self.command
+                    .create_followup_message(self.ctx, |message| message.content(content))
+                    .await,
+            ),
+        }
+    }
+
+    /// Remove the message once the preview is posted.
+    async fn finish(&self) {
+        if let Some(message) = self.message {
+            check_message(
+                self.command
+                    .delete_followup_message(self.ctx, message)
+                    .await,
+            );
+        }
+    }
+}
+
+/// Tell the user where their fx is in the render queue while it waits, the notice is removed once
+/// the render starts.
+async fn follow_queue(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    locale: Locale,
+    mut progress: watch::Receiver<Progress>,
+) {
+    let mut notice = None;
+    while progress.changed().await.is_ok() {
+        let current = *progress.borrow();
+        if !matches!(current, Progress::Queued(_)) {
+            break;
+        }
+        let content = progress_text(current, locale);
+        notice = match notice {
+            Some(message) => {
+                check_message(
+                    command
+                        .edit_followup_message(ctx, message, |edit| edit.content(content))
+                        .await,
+                );
+                Some(message)
+            }
+            None => match command
+                .create_followup_message(ctx, |message| message.content(content))
+                .await
+            {
+                Ok(message) => Some(message.id),
+                Err(why) => {
+                    log::error!("fail to post the queue position, {:?}", why);
+                    break;
+                }
+            },
+        };
+        tokio::time::sleep(PROGRESS_INTERVAL).await;
+    }
+    if let Some(message) = notice {
+        check_message(command.delete_followup_message(ctx, message).await);
+    }
 }
 
 struct RandomMessage<'m>(&'m [&'static str]);
@@ -239,72 +770,68 @@ where
     pub(crate) fn new(controller: &'a Controller<C, R>, data: &'a InteractionDataRegistry) -> Self {
         Self { controller, data }
     }
-    async fn post_processing(
+    /// Render the draft and post it for confirmation, showing how it goes on `processing`.
+    async fn preview(&self, processing: &Processing<'_>, fx: Fx, options: PreviewOptions) {
+        let (reporter, progress) = ProgressReporter::new();
+        let posting = async move {
+            let preview = self
+                .controller
+                .init_create_fx(fx, options, &reporter)
+                .await
+                .map_err(UserError::from)?;
+            reporter.report(Progress::Uploading);
+            self.post_preview(processing, preview)
+                .await
+                .map_err(UserError::from)
+        };
+        // following ends once the reporter is dropped with the posting
+        let (posted, ()) = futures::join!(posting, processing.follow(progress));
+        match posted {
+            Ok(_) => processing.finish().await,
+            Err(why) => processing.fail(&why).await,
+        }
+    }
+    /// Download the attachment and keep it as the original of the fx media.
+    async fn upload(&self, attachment: &Attachment, upload: &Upload) -> Result<(), CreateFxError> {
+        let data = attachment
+            .download()
+            .await
+            .map_err(CreateFxError::Serenity)?;
+        self.controller
+            .creator()
+            .upload(upload, &data)
+            .await
+            .map_err(CreateFxError::Upload)
+    }
+    async fn post_failed(
         ctx: &Context,
         interaction: &ApplicationCommandInteraction,
+        locale: Locale,
+        error: UserError,
     ) -> serenity::Result<Message> {
-        let random_message = RandomMessage::new(&[
-            "喵! 本毛正在處理你的要求，雞肉條在特價噎，你應該知道本毛在說什麼？",
-            "喵! 本毛正在處理你的要求",
-            "喵! 本毛喜歡雞肉條跟罐罐。還有...本毛正在處理你的要求",
-        ]);
         interaction
-            .create_followup_message(ctx, |message| message.content(random_message.next()))
+            .create_followup_message(ctx, |message| message.content(error.message(locale)))
             .await
     }
     async fn post_preview(
         &self,
-        ctx: &Context,
-        interaction: &ApplicationCommandInteraction,
+        processing: &Processing<'_>,
         preview: PreviewingFx,
     ) -> Result<Message, CreateFxError> {
-        let data = Cow::Borrowed(preview.media.as_slice());
         let create_data_result = self
             .data
             .create(InteractionData::CreatingFx(preview.fx.clone()))
             .await
             .map_err(CreateFxError::Data)?;
         let id = create_data_result.inserted_id.as_object_id().unwrap();
-        interaction
-            .create_followup_message(ctx, |response| {
-                let mut embed = CreateEmbed::default();
-                embed
-                    .colour(Colour::ORANGE)
-                    .title(&preview.fx.name)
-                    .description(preview.fx.description)
-                    .field("連結", preview.fx.media.url, false)
-                    .field(
-                        "開始秒數",
-                        format!("{}秒", preview.fx.media.start.as_secs()),
-                        false,
-                    )
-                    .field(
-                        "長度",
-                        format!("{}秒", preview.fx.media.length.as_secs()),
-                        false,
-                    );
+        let locale = processing.locale;
+        processing
+            .command
+            .create_followup_message(processing.ctx, |response| {
                 response
-                    .add_embed(embed)
-                    .add_file(AttachmentType::Bytes {
-                        data,
-                        filename: format!("preview_{}.mp3", preview.fx.name),
-                    })
-                    .components(|component| {
-                        component.create_action_row(|row| {
-                            row.create_button(|button| {
-                                button
-                                    .style(ButtonStyle::Primary)
-                                    .label("新增")
-                                    .custom_id(format!("{}:create", id.to_hex()))
-                            })
-                            .create_button(|button| {
-                                button
-                                    .style(ButtonStyle::Secondary)
-                                    .label("取消")
-                                    .custom_id(format!("{}:cancel", id.to_hex()))
-                            })
-                        })
-                    })
+                    .add_embed(preview_embed(&preview, locale))
+                    .add_files(preview_files(&preview))
+                    .set_components(preview_components(id, &preview.fx.media, locale))
             })
             .await
             .map_err(CreateFxError::Serenity)
@@ -313,25 +840,142 @@ where
     async fn post_invalid(
         ctx: &Context,
         interaction: &ApplicationCommandInteraction,
+        locale: Locale,
     ) -> serenity::Result<Message> {
         interaction
             .create_followup_message(ctx, |response| {
-                response.content("本毛Don't know WTF are you talking about. 喵!")
+                response.content(locale.text("invalid-command"))
             })
             .await
     }
-    fn option_fx(discord: DiscordOrigin, options: &[CommandDataOption]) -> Option<Fx> {
-        let start = options
-            .get(3)
+    /// The value of the option named by the message `id`. Discord always sends the names of the
+    /// default locale, whatever locale the user sees.
+    fn option<'o>(
+        options: &'o [CommandDataOption],
+        id: &str,
+    ) -> Option<&'o CommandDataOptionValue> {
+        let name = Locale::default().text(id);
+        options
+            .iter()
+            .find(|option| option.name == name)
             .and_then(|option| option.resolved.as_ref())
+    }
+    fn option_flag(options: &[CommandDataOption], id: &str) -> bool {
+        matches!(
+            Self::option(options, id),
+            Some(CommandDataOptionValue::Boolean(true))
+        )
+    }
+    fn option_string(options: &[CommandDataOption], id: &str) -> Option<String> {
+        Self::option(options, id).and_then(|value| match value {
+            CommandDataOptionValue::String(value) => Some(value.clone()),
+            _ => None,
+        })
+    }
+    fn option_attachment<'o>(options: &'o [CommandDataOption]) -> Option<&'o Attachment> {
+        Self::option(options, "option-attachment").and_then(|value| match value {
+            CommandDataOptionValue::Attachment(attachment) => Some(attachment),
+            _ => None,
+        })
+    }
+    fn option_speech(discord: DiscordOrigin, options: &[CommandDataOption]) -> Option<Fx> {
+        let name = Self::option_string(options, "option-name")?;
+        let text = Self::option_string(options, "option-text")?;
+        let voice = Self::option_string(options, "option-voice");
+        // voices are names like `en-us` or `zh+f2`, never something taken as an option
+        let is_valid_voice = voice.as_deref().map_or(true, |voice| {
+            !voice.is_empty()
+                && !voice.starts_with('-')
+                && voice
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_+-".contains(c))
+        });
+        if text.trim().is_empty() || text.chars().count() > MAX_SPEECH_CHARS || !is_valid_voice {
+            return None;
+        }
+        Some(Fx {
+            name,
+            description: text.clone(),
+            media: MediaOrigin {
+                source: Source::Speech {
+                    speech: Speech { text, voice },
+                },
+                start: Duration::ZERO,
+                length: MAX_SPEECH_LENGTH,
+                effects: vec![],
+            },
+            discord,
+            metadata: None,
+        })
+    }
+    fn option_number(options: &[CommandDataOption], id: &str) -> Option<f64> {
+        Self::option(options, id).and_then(|value| match value {
+            CommandDataOptionValue::Number(value) => Some(*value),
+            _ => None,
+        })
+    }
+    fn option_integer(options: &[CommandDataOption], name: &str) -> Option<i64> {
+        Self::option(options, name).and_then(|value| match value {
+            CommandDataOptionValue::Integer(value) => Some(*value),
+            _ => None,
+        })
+    }
+    /// Effects chosen by the options, in a fixed order so fades apply to the edges of the result.
+    fn option_effects(options: &[CommandDataOption]) -> Option<Vec<Effect>> {
+        let mut effects = vec![];
+        if let Some(factor) = Self::option_number(options, "option-speed") {
+            let percent = (factor * 100.0).round() as u32;
+            if percent != 100 {
+                effects.push(Effect::Tempo { percent });
+            }
+        }
+        if let Some(semitones) =
+            Self::option_integer(options, "option-pitch").filter(|value| *value != 0)
+        {
+            effects.push(Effect::Pitch {
+                semitones: semitones as i32,
+            });
+        }
+        if Self::option_flag(options, "option-reverse") {
+            effects.push(Effect::Reverse);
+        }
+        if let Some(delay) = Self::option_integer(options, "option-echo") {
+            effects.push(Effect::Echo {
+                delay_millis: delay as u32,
+                decay_percent: ECHO_DECAY_PERCENT,
+            });
+        }
+        if let Some(gain) = Self::option_integer(options, "option-bass").filter(|value| *value != 0)
+        {
+            effects.push(Effect::BassBoost {
+                gain_db: gain as i32,
+            });
+        }
+        if let Some(secs) = Self::option_number(options, "option-fade-in") {
+            let millis = (secs * 1000.0).round() as u32;
+            if millis > 0 {
+                effects.push(Effect::FadeIn { millis });
+            }
+        }
+        if let Some(secs) = Self::option_number(options, "option-fade-out") {
+            let millis = (secs * 1000.0).round() as u32;
+            if millis > 0 {
+                effects.push(Effect::FadeOut { millis });
+            }
+        }
+        effects
+            .iter()
+            .all(|effect| effect.validate().is_ok())
+            .then(|| effects)
+    }
+    fn option_fx(discord: DiscordOrigin, options: &[CommandDataOption]) -> Option<Fx> {
+        let start = Self::option(options, "option-start")
             .map(|value| match value {
                 CommandDataOptionValue::Integer(value) => *value as u64,
                 _ => 0,
             })
             .unwrap_or(0_u64);
-        let length = options
-            .get(4)
-            .and_then(|option| option.resolved.as_ref())
+        let length = Self::option(options, "option-duration")
             .map(|value| match value {
                 CommandDataOptionValue::Integer(value) => {
                     let value = *value;
@@ -346,55 +990,64 @@ where
             .unwrap_or(5_u64);
         FxArgument {
             discord,
-            name: options
-                .get(0)
-                .and_then(|option| option.resolved.as_ref())
-                .and_then(|value| match value {
-                    CommandDataOptionValue::String(value) => Some(value.clone()),
-                    _ => None,
-                }),
-            description: options
-                .get(1)
-                .and_then(|option| option.resolved.as_ref())
-                .and_then(|value| match value {
-                    CommandDataOptionValue::String(value) => Some(value.clone()),
-                    _ => None,
-                }),
-            url: options
-                .get(2)
-                .and_then(|option| option.resolved.as_ref())
-                .and_then(|value| match value {
-                    CommandDataOptionValue::String(value) => Some(value.clone()),
-                    _ => None,
-                }),
+            name: Self::option_string(options, "option-name"),
+            description: Self::option_string(options, "option-description"),
+            url: Self::option_string(options, "option-source"),
+            attachment: Self::option_attachment(options),
             start,
             length,
+            effects: Self::option_effects(options)?,
         }
         .to_fx()
     }
 }
 
-struct FxArgument {
+struct FxArgument<'a> {
     name: Option<String>,
     description: Option<String>,
     url: Option<String>,
+    attachment: Option<&'a Attachment>,
     start: u64,
     length: u64,
+    effects: Vec<Effect>,
     discord: DiscordOrigin,
 }
 
-impl FxArgument {
+impl<'a> FxArgument<'a> {
+    fn is_media(attachment: &Attachment) -> bool {
+        let is_media_type = attachment
+            .content_type
+            .as_ref()
+            .map_or(true, |content_type| {
+                content_type.starts_with("audio/") || content_type.starts_with("video/")
+            });
+        is_media_type && attachment.size <= MAX_ATTACHMENT_SIZE
+    }
+
     fn to_fx(self) -> Option<Fx> {
-        if self.name.is_some() && self.description.is_some() && self.url.is_some() {
+        let source = match (self.url, self.attachment) {
+            (Some(url), None) => Source::Url { url },
+            (None, Some(attachment)) if Self::is_media(attachment) => Source::Attachment {
+                attachment: Upload {
+                    id: attachment.id,
+                    filename: attachment.filename.clone(),
+                    url: attachment.url.clone(),
+                },
+            },
+            _ => return None,
+        };
+        if self.name.is_some() && self.description.is_some() {
             return Some(Fx {
                 name: self.name.unwrap(),
                 description: self.description.unwrap(),
                 media: MediaOrigin {
-                    url: self.url.unwrap(),
+                    source,
                     start: Duration::from_secs(self.start),
                     length: Duration::from_secs(self.length),
+                    effects: self.effects,
                 },
                 discord: self.discord,
+                metadata: None,
             });
         }
         None
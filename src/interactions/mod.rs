@@ -6,7 +6,15 @@ use serenity::{
     },
 };
 
-use crate::fx::{Controller, Creator, Fx, Repository};
+use crate::{
+    audio::{join_channel, media_stream_to_songbird_input, try_play_source},
+    discord::{AuthorVoiceChannelFinder, ComponentWrapper, Localized},
+    error::{Reason, UserError, UserFacing},
+    fx::{
+        progress::ProgressReporter, queue::JobOwner, Controller, Creator, Fx, Nudge,
+        PreviewOptions, Repository,
+    },
+};
 
 use self::data::InteractionData;
 
@@ -38,6 +46,12 @@ enum CustomIDParseError {
     MalformedID(bson::oid::Error),
 }
 
+impl UserFacing for CustomIDParseError {
+    fn reason(&self) -> Reason {
+        Reason::Stale
+    }
+}
+
 pub struct ButtonHandler<'a, C, R>
 where
     C: Creator,
@@ -59,28 +73,31 @@ where
         }
     }
     pub async fn handle(&self, ctx: &Context, interaction: &MessageComponentInteraction) {
-        let MessageComponentIntent { id, .. } =
+        let MessageComponentIntent { id, action } =
             match MessageComponentIntent::try_from(interaction.data.custom_id.as_str()) {
                 Ok(intent) => intent,
                 Err(why) => {
                     log::error!(
-                        "receiving a malformed custom_id {}, error: {:?}",
-                        &interaction.data.custom_id,
-                        why,
+                        "receiving a malformed custom_id {}",
+                        &interaction.data.custom_id
                     );
+                    Self::respond(ctx, interaction, why.into()).await;
                     return;
                 }
             };
         match self.data.get(id).await {
-            Ok(Some(InteractionData::CreatingFx(fx))) => {
-                self.handle_create(ctx, interaction, fx).await;
-            }
+            Ok(Some(InteractionData::CreatingFx(fx))) => match action.as_deref() {
+                Some(fx::LISTEN_ACTION) => self.handle_listen(ctx, interaction, fx).await,
+                action => match action.and_then(fx::nudge) {
+                    Some(nudge) => self.handle_nudge(ctx, interaction, id, fx, nudge).await,
+                    None => self.handle_create(ctx, interaction, fx).await,
+                },
+            },
             Ok(None) => {
-                self.report_staled(ctx, interaction).await;
-            }
-            Err(why) => {
-                log::error!("error while retriving interaction data {:?}", why);
+                let error = UserError::new(Reason::Stale, &format!("no interaction data {}", id));
+                Self::respond(ctx, interaction, error).await;
             }
+            Err(why) => Self::respond(ctx, interaction, why.into()).await,
         };
     }
 
@@ -91,28 +108,175 @@ where
         fx: Fx,
     ) {
         if let Err(why) = self.controller.confirm_create(fx).await {
-            log::error!("{:?}", why);
+            Self::respond(ctx, interaction, why.into()).await;
+            return;
         }
+        let content = ComponentWrapper(ctx, interaction)
+            .locale()
+            .text("create-succeeded");
         if let Err(why) = interaction
             .create_interaction_response(ctx, |message| {
                 message
                     .kind(InteractionResponseType::ChannelMessageWithSource)
-                    .interaction_response_data(|data| data.ephemeral(true).content("新增成功!"))
+                    .interaction_response_data(|data| data.ephemeral(true).content(content))
+            })
+            .await
+        {
+            log::error!("{:?}", why);
+        }
+    }
+
+    /// Move the cut of the draft, then render it again in place of the preview.
+    async fn handle_nudge(
+        &self,
+        ctx: &Context,
+        interaction: &MessageComponentInteraction,
+        id: ObjectId,
+        fx: Fx,
+        nudge: Nudge,
+    ) {
+        let fx = Fx {
+            media: fx.media.nudged(nudge),
+            ..fx
+        };
+        if let Some(metadata) = fx
+            .metadata
+            .as_ref()
+            .filter(|metadata| !metadata.contains(&fx.media))
+        {
+            let error = UserError::new(
+                Reason::PastEnd(metadata.duration.unwrap_or_default()),
+                &fx.media,
+            );
+            Self::respond(ctx, interaction, error).await;
+            return;
+        }
+        // rendering takes longer than an interaction may wait for its response
+        if let Err(why) = interaction
+            .create_interaction_response(ctx, |message| {
+                message.kind(InteractionResponseType::DeferredUpdateMessage)
             })
             .await
+        {
+            log::error!("{:?}", why);
+            return;
+        }
+        // only the cut, a context around every shifted cut would be rendered anew for each click
+        let preview = match self
+            .controller
+            .init_create_fx(fx, PreviewOptions::default(), &ProgressReporter::default())
+            .await
+        {
+            Ok(preview) => preview,
+            Err(why) => {
+                Self::report(ctx, interaction, why.into()).await;
+                return;
+            }
+        };
+        if let Err(why) = self
+            .data
+            .update(id, InteractionData::CreatingFx(preview.fx.clone()))
+            .await
+        {
+            Self::report(ctx, interaction, why.into()).await;
+            return;
+        }
+        let locale = ComponentWrapper(ctx, interaction).locale();
+        let mut message = interaction.message.clone();
+        if let Err(why) = message
+            .edit(ctx, |edit| {
+                for attachment in interaction.message.attachments.iter() {
+                    edit.remove_existing_attachment(attachment.id);
+                }
+                for file in fx::preview_files(&preview) {
+                    edit.attachment(file);
+                }
+                edit.set_embed(fx::preview_embed(&preview, locale))
+                    .set_components(fx::preview_components(id, &preview.fx.media, locale))
+            })
+            .await
+        {
+            Self::report(ctx, interaction, why.into()).await;
+        }
+    }
+
+    /// Play the draft in the voice channel of the clicker, nothing about the draft changes.
+    async fn handle_listen(
+        &self,
+        ctx: &Context,
+        interaction: &MessageComponentInteraction,
+        fx: Fx,
+    ) {
+        // joining and creating the media may take longer than an interaction may wait
+        if let Err(why) = interaction
+            .create_interaction_response(ctx, |message| {
+                message.kind(InteractionResponseType::DeferredUpdateMessage)
+            })
+            .await
+        {
+            log::error!("{:?}", why);
+            return;
+        }
+        let (guild_id, channel_id) = match ComponentWrapper(ctx, interaction)
+            .find_user_voice_channel()
+            .await
+        {
+            Ok(Some(channel)) => channel,
+            Ok(None) => {
+                let error = UserError::new(Reason::UserNotInVoice, &"the clicker is not in voice");
+                Self::report(ctx, interaction, error).await;
+                return;
+            }
+            Err(why) => {
+                Self::report(ctx, interaction, why.into()).await;
+                return;
+            }
+        };
+        if let Err(why) = join_channel(ctx, guild_id, channel_id).await {
+            Self::report(ctx, interaction, why.into()).await;
+            return;
+        }
+        let owner = JobOwner {
+            guild: Some(guild_id),
+            user: Some(interaction.user.id),
+        };
+        // the update is deferred without a message to show the position in the queue on
+        let progress = ProgressReporter::default();
+        let media = match self.controller.draft_media(&fx, owner, &progress).await {
+            Ok(media) => media,
+            Err(why) => {
+                Self::report(ctx, interaction, why.into()).await;
+                return;
+            }
+        };
+        let played = match media_stream_to_songbird_input(media).await {
+            Ok(input) => try_play_source(ctx, guild_id, input).await,
+            Err(why) => Err(why),
+        };
+        if let Err(why) = played {
+            Self::report(ctx, interaction, why.into()).await;
+        }
+    }
+
+    /// Tell only the clicker, once the interaction is responded.
+    async fn report(ctx: &Context, interaction: &MessageComponentInteraction, error: UserError) {
+        let content = error.message(ComponentWrapper(ctx, interaction).locale());
+        if let Err(why) = interaction
+            .create_followup_message(ctx, |message| message.ephemeral(true).content(content))
+            .await
         {
             log::error!("{:?}", why);
         }
     }
 
-    async fn report_staled(&self, ctx: &Context, interaction: &MessageComponentInteraction) {
+    /// Tell only the clicker, as the response of the interaction.
+    async fn respond(ctx: &Context, interaction: &MessageComponentInteraction, error: UserError) {
+        let content = error.message(ComponentWrapper(ctx, interaction).locale());
         if let Err(why) = interaction
             .create_interaction_response(ctx, |message| {
                 message
                     .kind(InteractionResponseType::ChannelMessageWithSource)
-                    .interaction_response_data(|data| {
-                        data.ephemeral(true).content("本毛忘了，請重新呼叫指令")
-                    })
+                    .interaction_response_data(|data| data.ephemeral(true).content(content))
             })
             .await
         {
@@ -1,189 +1,352 @@
+use bytes::Bytes;
 use std::{
-    collections::{hash_map::Iter as HashMapIter, HashMap},
-    io::{self, Cursor, Read},
-    sync::mpsc::{self, Receiver, Sender},
+    io::{self, Read, Seek, SeekFrom},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
 };
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+};
+
+/// Errors are passed to taps by kind and message, as `io::Error` cannot be cloned.
+type Failure = (io::ErrorKind, String);
+type Chunk = Result<Bytes, Failure>;
 
-struct Registry<M> {
-    auto_increment: u64,
-    members: HashMap<u64, M>,
+#[derive(Clone)]
+enum SourceState {
+    Reading,
+    Finished,
+    Failed(Failure),
 }
 
-impl<M> Default for Registry<M> {
-    fn default() -> Self {
-        Self {
-            auto_increment: 0,
-            members: HashMap::new(),
-        }
-    }
+struct Taps {
+    history: Vec<u8>,
+    state: SourceState,
+    senders: Vec<UnboundedSender<Chunk>>,
 }
 
-impl<M> Registry<M> {
-    fn register(&mut self, member: M) -> u64 {
-        let id = self.auto_increment;
-        self.members.insert(id, member);
-        self.auto_increment += 1;
-        id
+impl Taps {
+    fn tap(&mut self) -> Tapped {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        if !self.history.is_empty() {
+            let _result = sender.send(Ok(Bytes::from(self.history.clone())));
+        }
+        match &self.state {
+            SourceState::Reading => self.senders.push(sender),
+            SourceState::Failed(failure) => {
+                let _result = sender.send(Err(failure.clone()));
+            }
+            SourceState::Finished => (),
+        }
+        Tapped {
+            current_slice: None,
+            receiver: Some(receiver),
+        }
     }
 
-    fn unregister(&mut self, id: u64) {
-        self.members.remove(&id);
+    fn send(&mut self, chunk: Chunk) {
+        // taps that have been dropped are unregistered here
+        self.senders
+            .retain(|sender| sender.send(chunk.clone()).is_ok());
     }
 
-    fn iter(&self) -> HashMapIter<'_, u64, M> {
-        self.members.iter()
+    fn close(&mut self, state: SourceState) {
+        self.state = state;
+        self.senders.clear();
     }
 }
 
 pub struct Tapped {
-    tap_id: u64,
-    unregister: Sender<u64>,
-    current_slice: Option<Cursor<Vec<u8>>>,
-    receiver: Option<Receiver<Vec<u8>>>,
-}
-
-impl Tapped {
-    fn read_next_slice(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        match self
-            .receiver
-            .as_mut()
-            .and_then(|receiver| receiver.recv().ok())
-        {
-            Some(slice) => {
-                let mut slice = Cursor::new(slice);
-                let result = slice.read(buf);
-                self.current_slice = Some(slice);
-                result
+    current_slice: Option<Bytes>,
+    receiver: Option<UnboundedReceiver<Chunk>>,
+}
+
+impl AsyncRead for Tapped {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(slice) = this.current_slice.as_mut() {
+                if !slice.is_empty() {
+                    let n = slice.len().min(buf.remaining());
+                    buf.put_slice(&slice.split_to(n));
+                    return Poll::Ready(Ok(()));
+                }
+                this.current_slice = None;
             }
-            None => {
-                self.receiver = None;
-                Ok(0)
+            let receiver = match this.receiver.as_mut() {
+                Some(receiver) => receiver,
+                None => return Poll::Ready(Ok(())),
+            };
+            match receiver.poll_recv(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(Ok(slice))) => this.current_slice = Some(slice),
+                Poll::Ready(Some(Err((kind, message)))) => {
+                    this.receiver = None;
+                    return Poll::Ready(Err(io::Error::new(kind, message)));
+                }
+                Poll::Ready(None) => {
+                    this.receiver = None;
+                    return Poll::Ready(Ok(()));
+                }
             }
         }
     }
-
-    fn read_current_slice(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        let result = self.current_slice.as_mut().unwrap().read(buf);
-        if let Ok(0) = result {
-            return self.read_next_slice(buf);
-        }
-        result
-    }
 }
 
-impl Drop for Tapped {
-    fn drop(&mut self) {
-        let _result = self.unregister.send(self.tap_id);
-    }
+/// Creates taps of a [`TappableReader`] without access to the reader itself.
+#[derive(Clone)]
+pub struct Tapper {
+    taps: Arc<Mutex<Taps>>,
 }
 
-impl Read for Tapped {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        if self.current_slice.is_none() {
-            self.read_next_slice(buf)
-        } else {
-            self.read_current_slice(buf)
-        }
+impl Tapper {
+    pub fn tap(&self) -> Tapped {
+        self.taps.lock().unwrap().tap()
     }
 }
 
+/// Reader that copies everything read from `source` to its taps. What has been read so far is
+/// kept, so a tap created in the middle of the stream still receives it from the beginning.
 pub struct TappableReader<R>
 where
-    R: Read,
+    R: AsyncRead + Unpin,
 {
     source: R,
-    taps: Registry<Sender<Vec<u8>>>,
-    shutdown: Receiver<u64>,
-    shutdown_sender: Sender<u64>,
+    taps: Arc<Mutex<Taps>>,
 }
 
-impl<R: Read> TappableReader<R> {
+impl<R: AsyncRead + Unpin> TappableReader<R> {
     pub fn new(source: R) -> Self {
-        let (sender, receiver) = mpsc::channel();
         Self {
             source,
-            taps: Registry::default(),
-            shutdown: receiver,
-            shutdown_sender: sender,
+            taps: Arc::new(Mutex::new(Taps {
+                history: vec![],
+                state: SourceState::Reading,
+                senders: vec![],
+            })),
         }
     }
-    pub fn tap(&mut self) -> Tapped {
-        let (sender, receiver) = mpsc::channel();
-        let tap_id = self.taps.register(sender);
-        Tapped {
-            current_slice: None,
-            tap_id,
-            receiver: Some(receiver),
-            unregister: self.shutdown_sender.clone(),
+    pub fn tap(&self) -> Tapped {
+        self.taps.lock().unwrap().tap()
+    }
+
+    pub fn tapper(&self) -> Tapper {
+        Tapper {
+            taps: self.taps.clone(),
         }
     }
 }
 
-impl<R: Read> Read for TappableReader<R> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        match self.source.read(buf) {
-            Ok(n) => {
-                self.send_to_taps(&buf[..n]);
-                Ok(n)
+impl<R: AsyncRead + Unpin> AsyncRead for TappableReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        match Pin::new(&mut this.source).poll_read(cx, buf) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Ok(())) => {
+                let read = &buf.filled()[filled..];
+                let mut taps = this.taps.lock().unwrap();
+                if !read.is_empty() {
+                    taps.history.extend_from_slice(read);
+                    taps.send(Ok(Bytes::copy_from_slice(read)));
+                } else if buf.remaining() > 0 {
+                    taps.close(SourceState::Finished);
+                }
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Err(err)) if err.kind() == io::ErrorKind::Interrupted => {
+                Poll::Ready(Err(err))
             }
-            Err(err) => {
-                self.close_taps();
-                Err(err)
+            Poll::Ready(Err(err)) => {
+                let mut taps = this.taps.lock().unwrap();
+                let failure = (err.kind(), err.to_string());
+                taps.send(Err(failure.clone()));
+                taps.close(SourceState::Failed(failure));
+                Poll::Ready(Err(err))
             }
         }
     }
 }
 
-impl<R: Read> TappableReader<R> {
-    fn reconcile_taps(&mut self) {
-        while let Ok(to_unregister) = self.shutdown.try_recv() {
-            self.taps.unregister(to_unregister);
+/// Makes a forward-only reader seekable by keeping what has been read from it. Data is pulled
+/// from `source` only as far as reads and seeks require, so consumers can start on a stream that
+/// is still being produced.
+pub struct SeekableStream<R: Read> {
+    source: Option<R>,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> SeekableStream<R> {
+    pub fn new(source: R) -> Self {
+        Self {
+            source: Some(source),
+            buffer: vec![],
+            position: 0,
         }
     }
-    fn send_to_taps(&mut self, data: &[u8]) {
-        self.reconcile_taps();
-        let slice = Vec::from(data);
-        for (_, sender) in self.taps.iter() {
-            sender.send(slice.clone()).unwrap();
+
+    /// Read from the source until `len` bytes are buffered or the source ends.
+    fn fill_to(&mut self, len: usize) -> io::Result<()> {
+        let mut chunk = [0; 8192];
+        while self.buffer.len() < len {
+            let source = match self.source.as_mut() {
+                Some(source) => source,
+                None => break,
+            };
+            match source.read(&mut chunk) {
+                Ok(0) => self.source = None,
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
         }
+        Ok(())
     }
+}
 
-    fn close_taps(&mut self) {
-        for sender in self.taps.iter() {
-            drop(sender);
+impl<R: Read> Read for SeekableStream<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.fill_to(self.position + buf.len().min(1))?;
+        let available = &self.buffer[self.position.min(self.buffer.len())..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+impl<R: Read> Seek for SeekableStream<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.position as i64 + offset,
+            SeekFrom::End(offset) => {
+                self.fill_to(usize::MAX)?;
+                self.buffer.len() as i64 + offset
+            }
+        };
+        if target < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek to a negative position",
+            ));
         }
+        self.position = target as usize;
+        Ok(self.position as u64)
+    }
+}
+
+/// A reader shared by clones, each one moves the same position. Lets a decoder that owns its
+/// reader be recreated over the same data.
+pub struct SharedReader<R>(Arc<Mutex<R>>);
+
+impl<R> SharedReader<R> {
+    pub fn new(source: R) -> Self {
+        Self(Arc::new(Mutex::new(source)))
+    }
+}
+
+impl<R> Clone for SharedReader<R> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<R: Read> Read for SharedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().read(buf)
+    }
+}
+
+impl<R: Seek> Seek for SharedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.lock().unwrap().seek(pos)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use bytes::Buf;
-    use std::io::{Cursor, Read};
+    use std::io::{Read, Seek, SeekFrom};
+    use tokio::io::AsyncReadExt;
 
-    use super::TappableReader;
+    use super::{SeekableStream, TappableReader};
 
     fn str_to_bytes_vec(content: &str) -> Vec<u8> {
         content.as_bytes().to_vec()
     }
 
-    #[test]
-    fn test_normal_read() {
-        let reader = Cursor::new(b"hello world").reader();
-        let mut reader = TappableReader::new(reader);
+    #[tokio::test]
+    async fn test_normal_read() {
+        let mut reader = TappableReader::new(&b"hello world"[..]);
         let mut output = vec![];
-        reader.read_to_end(&mut output).unwrap();
+        reader.read_to_end(&mut output).await.unwrap();
         assert_eq!(str_to_bytes_vec("hello world"), output);
     }
 
-    #[test]
-    fn test_tapping_read() {
-        let reader = Cursor::new(b"hello world").reader();
-        let mut reader = TappableReader::new(reader);
+    #[tokio::test]
+    async fn test_tapping_read() {
+        let mut reader = TappableReader::new(&b"hello world"[..]);
         let mut tapped = reader.tap();
         let mut output = vec![];
-        reader.read_to_end(&mut output).unwrap();
+        reader.read_to_end(&mut output).await.unwrap();
         let mut tapped_output = vec![];
-        tapped.read_to_end(&mut tapped_output).unwrap();
+        tapped.read_to_end(&mut tapped_output).await.unwrap();
         assert_eq!(str_to_bytes_vec("hello world"), tapped_output);
     }
+
+    #[tokio::test]
+    async fn test_late_tap_read() {
+        let mut reader = TappableReader::new(&b"hello world"[..]);
+        let mut head = [0; 5];
+        reader.read_exact(&mut head).await.unwrap();
+        let mut tapped = reader.tapper().tap();
+        let mut output = vec![];
+        reader.read_to_end(&mut output).await.unwrap();
+        let mut tapped_output = vec![];
+        tapped.read_to_end(&mut tapped_output).await.unwrap();
+        assert_eq!(str_to_bytes_vec("hello world"), tapped_output);
+    }
+
+    #[tokio::test]
+    async fn test_tap_after_finished() {
+        let mut reader = TappableReader::new(&b"hello world"[..]);
+        let mut output = vec![];
+        reader.read_to_end(&mut output).await.unwrap();
+        let mut tapped = reader.tap();
+        let mut tapped_output = vec![];
+        tapped.read_to_end(&mut tapped_output).await.unwrap();
+        assert_eq!(str_to_bytes_vec("hello world"), tapped_output);
+    }
+
+    #[test]
+    fn test_seekable_stream_seek_back() {
+        let mut stream = SeekableStream::new(&b"hello world"[..]);
+        let mut head = [0; 5];
+        stream.read_exact(&mut head).unwrap();
+        stream.seek(SeekFrom::Start(0)).unwrap();
+        let mut output = vec![];
+        stream.read_to_end(&mut output).unwrap();
+        assert_eq!(str_to_bytes_vec("hello world"), output);
+    }
+
+    #[test]
+    fn test_seekable_stream_seek_ahead() {
+        let mut stream = SeekableStream::new(&b"hello world"[..]);
+        stream.seek(SeekFrom::Start(6)).unwrap();
+        let mut output = vec![];
+        stream.read_to_end(&mut output).unwrap();
+        assert_eq!(str_to_bytes_vec("world"), output);
+        assert_eq!(11, stream.seek(SeekFrom::End(0)).unwrap());
+    }
 }
@@ -2,8 +2,12 @@ mod audio;
 pub mod bot;
 pub mod config;
 mod discord;
+pub mod error;
 pub mod fx;
+pub mod i18n;
 mod interactions;
 mod ioutils;
 pub mod log;
 pub mod music;
+pub mod tools;
+mod waveform;
@@ -1,9 +1,10 @@
 use crate::{
     audio::{
-        join_channel, stop_for_guild, try_join_authors_channel, try_parse_voice_channel_id,
-        try_play_file, try_play_ytdl, PlayError,
+        join_channel, now_playing, stop_for_guild, try_join_authors_channel,
+        try_parse_voice_channel_id, try_play_file, try_play_ytdl, PlayError,
     },
-    discord::{check_serenity_result, MessageWrapper},
+    discord::{check_serenity_result, Localized, MessageWrapper},
+    error::{Reason, UserError},
 };
 use serenity::{
     client::Context,
@@ -13,11 +14,24 @@ use serenity::{
     },
     model::channel::Message,
 };
+use songbird::tracks::TrackHandle;
+use std::time::Duration;
 
 #[group]
-#[commands(join, play, stop, tbc, pwtf)]
+#[commands(join, play, stop, seek, loop_track, tbc, pwtf)]
 struct Music;
 
+async fn reply_error(ctx: &Context, msg: &Message, error: UserError) {
+    let locale = MessageWrapper(ctx, msg).locale();
+    check_serenity_result(msg.reply(ctx, error.message(locale)).await);
+}
+
+/// Reply with the message `id` of the catalog.
+async fn reply_text(ctx: &Context, msg: &Message, id: &str) {
+    let locale = MessageWrapper(ctx, msg).locale();
+    check_serenity_result(msg.reply(ctx, locale.text(id)).await);
+}
+
 #[command]
 async fn join(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
     let guild_id = match msg.guild_id {
@@ -31,12 +45,11 @@ async fn join(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
         Some(id) => match try_parse_voice_channel_id(ctx, &id).await {
             Some(id) => {
                 if let Err(why) = join_channel(ctx, guild_id, id).await {
-                    check_serenity_result(msg.reply(ctx, "本毛無法加入您的頻道").await);
-                    log::error!("fail to join the channel, err: {:?}", why);
+                    reply_error(ctx, msg, why.into()).await;
                 }
             }
             None => {
-                check_serenity_result(msg.reply(ctx, "無效的id").await);
+                reply_text(ctx, msg, "invalid-channel-id").await;
                 return Ok(());
             }
         },
@@ -53,44 +66,41 @@ async fn play(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
     let url = match args.single::<String>() {
         Ok(url) => url,
         Err(_) => {
-            check_serenity_result(msg.reply(&ctx.http, "用法!play <URL>").await);
+            reply_text(ctx, msg, "usage-play").await;
             return Ok(());
         }
     };
     let guild = msg.guild(&ctx.cache).unwrap();
     let guild_id = guild.id;
     try_join_authors_channel(ctx, MessageWrapper(ctx, msg)).await;
-    if let Err(why) = try_play_ytdl(ctx, msg, &url, guild_id).await {
-        log::error!("fail to play {:?}", why);
+    if let Err(why) = try_play_ytdl(ctx, &url, guild_id).await {
+        reply_error(ctx, msg, why.into()).await;
     }
     Ok(())
 }
 
-#[command]
-async fn tbc(ctx: &Context, msg: &Message) -> CommandResult {
+/// Play a bundled sound in the voice channel of the author, telling them when it can't be.
+async fn play_resource(ctx: &Context, msg: &Message, path: &str) {
     try_join_authors_channel(ctx, MessageWrapper(ctx, msg)).await;
-    match try_play_file(ctx, msg.guild_id.unwrap(), "./resources/tc.mp3").await {
-        Err(err) if err == PlayError::NotInChannel => {
-            try_play_file(ctx, msg.guild_id.unwrap(), "./resources/tc.mp3")
-                .await
-                .unwrap();
-        }
-        _ => (),
+    let guild_id = msg.guild_id.unwrap();
+    let played = match try_play_file(ctx, guild_id, path).await {
+        Err(PlayError::NotInChannel) => try_play_file(ctx, guild_id, path).await,
+        played => played,
     };
+    if let Err(why) = played {
+        reply_error(ctx, msg, why.into()).await;
+    }
+}
+
+#[command]
+async fn tbc(ctx: &Context, msg: &Message) -> CommandResult {
+    play_resource(ctx, msg, "./resources/tc.mp3").await;
     Ok(())
 }
 
 #[command]
 async fn pwtf(ctx: &Context, msg: &Message) -> CommandResult {
-    try_join_authors_channel(ctx, MessageWrapper(ctx, msg)).await;
-    match try_play_file(ctx, msg.guild_id.unwrap(), "./resources/pwtf.mp3").await {
-        Err(err) if err == PlayError::NotInChannel => {
-            try_play_file(ctx, msg.guild_id.unwrap(), "./resources/pwtf.mp3")
-                .await
-                .unwrap();
-        }
-        _ => (),
-    };
+    play_resource(ctx, msg, "./resources/pwtf.mp3").await;
     Ok(())
 }
 
@@ -100,3 +110,61 @@ async fn stop(ctx: &Context, msg: &Message) -> CommandResult {
     stop_for_guild(ctx, guild_id).await;
     Ok(())
 }
+
+/// The track playing in the guild of `msg` if it can be sought, the author is told otherwise.
+async fn seekable_track(ctx: &Context, msg: &Message) -> Option<TrackHandle> {
+    let track = match now_playing(ctx, msg.guild_id?).await {
+        Some(track) => track,
+        None => {
+            let error = UserError::new(Reason::NotPlaying, &"no track has been played");
+            reply_error(ctx, msg, error).await;
+            return None;
+        }
+    };
+    if !track.is_seekable() {
+        let error = UserError::new(Reason::NotSeekable, &"the track is not seekable");
+        reply_error(ctx, msg, error).await;
+        return None;
+    }
+    Some(track)
+}
+
+#[command]
+async fn seek(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let position = match args.single::<f64>() {
+        Ok(secs) if secs.is_finite() && secs >= 0.0 => Duration::from_secs_f64(secs),
+        _ => {
+            reply_text(ctx, msg, "usage-seek").await;
+            return Ok(());
+        }
+    };
+    if let Some(track) = seekable_track(ctx, msg).await {
+        if let Err(why) = track.seek_time(position) {
+            reply_error(ctx, msg, why.into()).await;
+        }
+    }
+    Ok(())
+}
+
+#[command("loop")]
+async fn loop_track(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let enable = match args.single::<String>().ok().as_deref() {
+        None | Some("on") => true,
+        Some("off") => false,
+        Some(_) => {
+            reply_text(ctx, msg, "usage-loop").await;
+            return Ok(());
+        }
+    };
+    if let Some(track) = seekable_track(ctx, msg).await {
+        let result = if enable {
+            track.enable_loop()
+        } else {
+            track.disable_loop()
+        };
+        if let Err(why) = result {
+            reply_error(ctx, msg, why.into()).await;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use serenity::prelude::TypeMapKey;
+use std::{io, process::Stdio, sync::Arc, time::Duration};
+use tokio::process::Command;
+
+use crate::config::{self, Downloader};
+
+#[derive(Clone, Debug)]
+pub struct ExternalTool {
+    pub program: String,
+    pub args: Vec<String>,
+    /// longest time a run of the tool may take
+    pub timeout: Duration,
+}
+
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl ExternalTool {
+    fn new(program: &str, args: &[String], timeout_secs: u64) -> Self {
+        Self {
+            program: program.to_string(),
+            args: args.to_vec(),
+            timeout: Duration::from_secs(timeout_secs),
+        }
+    }
+
+    /// Command of the tool with the configured extra arguments.
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        command
+    }
+
+    pub fn std_command(&self) -> std::process::Command {
+        let mut command = std::process::Command::new(&self.program);
+        command.args(&self.args);
+        command
+    }
+
+    /// First line printed by the tool for `version_flag`, fails if the tool cannot be run.
+    async fn version(&self, version_flag: &str) -> io::Result<String> {
+        let output = Command::new(&self.program)
+            .arg(version_flag)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(VERSION_TIMEOUT, output)
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "version check timed out"))??;
+        if !output.status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("{} exited with {}", self.program, output.status),
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string())
+    }
+}
+
+#[derive(Debug)]
+pub enum ToolDetectError {
+    NoDownloader,
+    FFmpeg(io::Error),
+}
+
+/// External programs available on the host.
+#[derive(Clone, Debug)]
+pub struct Toolchain {
+    pub downloader_kind: Downloader,
+    pub downloader: ExternalTool,
+    pub ffmpeg: ExternalTool,
+    /// text-to-speech engine, if present on the host
+    pub speech: Option<ExternalTool>,
+}
+
+impl TypeMapKey for Toolchain {
+    type Value = Arc<Toolchain>;
+}
+
+impl Toolchain {
+    /// Probe the configured tools and report their versions. The first downloader of
+    /// `config.downloaders` that can be run is picked.
+    pub async fn detect(config: &config::Tools) -> Result<Self, ToolDetectError> {
+        let ffmpeg = ExternalTool::new(
+            &config.ffmpeg,
+            &config.ffmpeg_args,
+            config.ffmpeg_timeout_secs,
+        );
+        let version = ffmpeg
+            .version("-version")
+            .await
+            .map_err(ToolDetectError::FFmpeg)?;
+        log::info!("found ffmpeg: {}", version);
+        let speech = ExternalTool::new(
+            &config.speech,
+            &config.speech_args,
+            config.speech_timeout_secs,
+        );
+        let speech = match speech.version("--version").await {
+            Ok(version) => {
+                log::info!("found text-to-speech engine: {}", version);
+                Some(speech)
+            }
+            Err(why) => {
+                log::warn!(
+                    "text-to-speech engine ({}) is unavailable, {:?}",
+                    speech.program,
+                    why
+                );
+                None
+            }
+        };
+        for kind in config.downloaders.iter() {
+            let downloader = match kind {
+                Downloader::YtDlp => ExternalTool::new(
+                    &config.yt_dlp,
+                    &config.yt_dlp_args,
+                    config.download_timeout_secs,
+                ),
+                Downloader::YoutubeDl => ExternalTool::new(
+                    &config.youtube_dl,
+                    &config.youtube_dl_args,
+                    config.download_timeout_secs,
+                ),
+            };
+            match downloader.version("--version").await {
+                Ok(version) => {
+                    log::info!("using {:?} {} as the downloader", kind, version);
+                    return Ok(Self {
+                        downloader_kind: *kind,
+                        downloader,
+                        ffmpeg,
+                        speech,
+                    });
+                }
+                Err(why) => {
+                    log::warn!(
+                        "{:?} ({}) is unavailable, {:?}",
+                        kind,
+                        downloader.program,
+                        why
+                    );
+                }
+            }
+        }
+        Err(ToolDetectError::NoDownloader)
+    }
+}
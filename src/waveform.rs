@@ -0,0 +1,192 @@
+use rodio::{Decoder, Source};
+use std::{io::Cursor, ops::Range, time::Duration};
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 160;
+const BACKGROUND: [u8; 3] = [0x2f, 0x31, 0x36];
+const SELECTED_BACKGROUND: [u8; 3] = [0x40, 0x3a, 0x36];
+/// Same as the orange of the preview embed.
+const SELECTED: [u8; 3] = [0xe6, 0x7e, 0x22];
+const CONTEXT: [u8; 3] = [0x72, 0x76, 0x7d];
+
+#[derive(Debug)]
+pub enum WaveformError {
+    Decode(rodio::decoder::DecoderError),
+    Encode(png::EncodingError),
+}
+
+/// Mono samples between -1 and 1.
+pub struct Samples {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+impl Samples {
+    fn duration(&self) -> Duration {
+        self.at(self.samples.len())
+    }
+
+    fn at(&self, index: usize) -> Duration {
+        Duration::from_secs_f64(index as f64 / self.sample_rate.max(1) as f64)
+    }
+
+    /// Lengths of the silence at the start and at the end, where no sample is louder than
+    /// `threshold_db` dBFS. Nothing counts as silence if the whole clip is silent.
+    pub fn silence(&self, threshold_db: f32) -> (Duration, Duration) {
+        let threshold = 10_f32.powf(threshold_db / 20.0);
+        let is_loud = |sample: &f32| sample.abs() > threshold;
+        match (
+            self.samples.iter().position(is_loud),
+            self.samples.iter().rposition(is_loud),
+        ) {
+            (Some(first), Some(last)) => (
+                self.at(first),
+                self.duration().saturating_sub(self.at(last + 1)),
+            ),
+            _ => (Duration::ZERO, Duration::ZERO),
+        }
+    }
+}
+
+pub fn decode_mp3(media: Vec<u8>) -> Result<Samples, WaveformError> {
+    let decoder = Decoder::new_mp3(Cursor::new(media)).map_err(WaveformError::Decode)?;
+    let channels = decoder.channels().max(1) as usize;
+    let sample_rate = decoder.sample_rate();
+    let interleaved: Vec<i16> = decoder.collect();
+    let samples = interleaved
+        .chunks(channels)
+        .map(|frame| {
+            let sum: f32 = frame
+                .iter()
+                .map(|sample| *sample as f32 / i16::MAX as f32)
+                .sum();
+            sum / frame.len() as f32
+        })
+        .collect();
+    Ok(Samples {
+        samples,
+        sample_rate,
+    })
+}
+
+/// Draw the waveform as a PNG. Outside of `selected` it's drawn dimmed, the whole waveform is
+/// selected without it.
+pub fn render(
+    samples: &Samples,
+    selected: Option<Range<Duration>>,
+) -> Result<Vec<u8>, WaveformError> {
+    let width = WIDTH as usize;
+    let height = HEIGHT as usize;
+    let duration = samples.duration();
+    let mut pixels = vec![0_u8; width * height * 3];
+    let bucket = (samples.samples.len() + width - 1) / width;
+    let middle = height as f32 / 2.0;
+    for x in 0..width {
+        let at = duration.mul_f64(x as f64 / width as f64);
+        let is_selected = selected.as_ref().map_or(true, |range| range.contains(&at));
+        let (background, foreground) = if is_selected {
+            (SELECTED_BACKGROUND, SELECTED)
+        } else {
+            (BACKGROUND, CONTEXT)
+        };
+        let column = samples.samples.iter().skip(x * bucket).take(bucket).fold(
+            None,
+            |extent: Option<(f32, f32)>, sample| match extent {
+                Some((low, high)) => Some((low.min(*sample), high.max(*sample))),
+                None => Some((*sample, *sample)),
+            },
+        );
+        // silence and the end of the clip still show as a flat line
+        let (low, high) = column.unwrap_or((0.0, 0.0));
+        let top = (middle - high.clamp(-1.0, 1.0) * middle).floor() as usize;
+        let bottom = (middle - low.clamp(-1.0, 1.0) * middle).ceil() as usize;
+        for y in 0..height {
+            let colour = if y >= top.min(height - 1) && y <= bottom.max(top) {
+                foreground
+            } else {
+                background
+            };
+            let offset = (y * width + x) * 3;
+            pixels[offset..offset + 3].copy_from_slice(&colour);
+        }
+    }
+    let mut png = vec![];
+    let mut encoder = png::Encoder::new(&mut png, WIDTH, HEIGHT);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(WaveformError::Encode)?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{render, Samples, CONTEXT, HEIGHT, SELECTED, WIDTH};
+
+    fn sine(secs: u32) -> Samples {
+        let sample_rate = 8000;
+        Samples {
+            samples: (0..sample_rate * secs)
+                .map(|i| (i as f32 * 440.0 * std::f32::consts::TAU / sample_rate as f32).sin())
+                .collect(),
+            sample_rate,
+        }
+    }
+
+    fn decode(png: &[u8]) -> Vec<u8> {
+        let decoder = png::Decoder::new(png);
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((WIDTH, HEIGHT), (info.width, info.height));
+        pixels
+    }
+
+    fn pixel(pixels: &[u8], x: u32, y: u32) -> [u8; 3] {
+        let offset = ((y * WIDTH + x) * 3) as usize;
+        [pixels[offset], pixels[offset + 1], pixels[offset + 2]]
+    }
+
+    #[test]
+    fn test_silence_at_both_ends() {
+        let mut samples = vec![0.0; 4000];
+        samples.extend(sine(1).samples);
+        samples.extend(vec![0.001; 2000]);
+        let samples = Samples {
+            samples,
+            sample_rate: 8000,
+        };
+        let (leading, trailing) = samples.silence(-50.0);
+        assert_eq!(500, leading.as_millis());
+        assert_eq!(250, trailing.as_millis());
+    }
+
+    #[test]
+    fn test_silent_clip_keeps_everything() {
+        let samples = Samples {
+            samples: vec![0.0; 8000],
+            sample_rate: 8000,
+        };
+        assert_eq!((Duration::ZERO, Duration::ZERO), samples.silence(-50.0));
+    }
+
+    #[test]
+    fn test_render_whole_clip_selected() {
+        let pixels = decode(&render(&sine(2), None).unwrap());
+        assert_eq!(SELECTED, pixel(&pixels, 0, HEIGHT / 2));
+        assert_eq!(SELECTED, pixel(&pixels, WIDTH - 1, HEIGHT / 2));
+    }
+
+    #[test]
+    fn test_render_highlights_selection() {
+        let selected = Duration::from_secs(1)..Duration::from_secs(3);
+        let pixels = decode(&render(&sine(4), Some(selected)).unwrap());
+        assert_eq!(CONTEXT, pixel(&pixels, WIDTH / 8, HEIGHT / 2));
+        assert_eq!(SELECTED, pixel(&pixels, WIDTH / 2, HEIGHT / 2));
+        assert_eq!(CONTEXT, pixel(&pixels, WIDTH - 1, HEIGHT / 2));
+    }
+}